@@ -356,10 +356,7 @@ impl Module for MyModule {
                 depth_texture_format: Some(wgpu::TextureFormat::Depth32Float),
                 ..Default::default()
             })
-            .with(Transform {
-                rot: Quat::identity(),
-                pos: vec3(0., 0., 5.)
-            })
+            .with(Transform::new().pos(vec3(0., 0., 5.)))
             .build();
     }
 }
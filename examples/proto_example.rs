@@ -8,7 +8,6 @@ use mu::ecs::Transform;
 use mu::util::Color;
 use mu::proto::*;
 use std::task::Poll;
-use std::sync::{Mutex, Arc};
 use imgui::*;
 
 struct MyModule;
@@ -28,10 +27,11 @@ impl<'a> System<'a> for MyGuiSystem {
                 .build(frame, || {
                 frame.input_text(im_str!("Target Path"), &mut self.target_path).build();
                 if frame.small_button(im_str!("Save")) {
-                    if let Poll::Ready(loaded_entities) = &*create_info.result_poll.lock().unwrap() {
-                        store_requests.push(ProtoStoreRequest::new(loaded_entities, &self.target_path.to_string()));
-                    } else {
-                        info!("Proto not yet created");
+                    match &*create_info.result_poll.lock().unwrap() {
+                        Poll::Ready(Ok(loaded_entities)) =>
+                            store_requests.push(ProtoStoreRequest::new(loaded_entities, &self.target_path.to_string())),
+                        Poll::Ready(Err(e)) => info!("Proto failed to load: {}", e),
+                        Poll::Pending => info!("Proto not yet created")
                     }
                 }
             });
@@ -40,7 +40,7 @@ impl<'a> System<'a> for MyGuiSystem {
 }
 
 struct ListenEntityCreateData {
-    result_poll: Arc<Mutex<Poll< Vec<Entity> >>>
+    result_poll: ProtoLoadResult
 }
 
 impl Module for MyModule {
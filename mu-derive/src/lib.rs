@@ -0,0 +1,192 @@
+//! Derive macros of mu engine.
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, Lit, Meta, NestedMeta, Type};
+
+/// Implements `mu::proto::ProtoComponent`, register with `InitContextProtoExt::add_proto_component`.
+///
+/// ```ignore
+/// #[derive(ProtoComponent)]
+/// #[proto(name = "Door", version = 2)]
+/// struct Door {
+///     open: bool,
+///     #[proto(entity)]
+///     switch: Entity,
+///     #[proto(resource)]
+///     sound: ResourceRef<Vec<u8>>,
+///     #[proto(skip)]
+///     timer: f32
+/// }
+/// ```
+///
+/// * `#[proto(name = "...")]` - Name used in json, defaults to the struct name.
+/// * `#[proto(version = N)]` - Version of the data format, defaults to 1. See `ComponentS11n::version`.
+/// * `#[proto(schema)]` - Generate `ProtoComponent::schema`, requires `JsonSchema` on the plain fields.
+/// * `#[proto(entity)]` - `Entity` or `Option<Entity>`, stored as index into the proto's entities.
+/// * `#[proto(resource)]` - `ResourceRef<T>` where `T: LoadableAsset`, stored as asset path.
+/// * `#[proto(skip)]` - Not stored, `Default::default()` when loaded.
+#[proc_macro_derive(ProtoComponent, attributes(proto))]
+pub fn derive_proto_component(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match impl_proto_component(&input) {
+        Ok(ts) => ts.into(),
+        Err(e) => e.to_compile_error().into()
+    }
+}
+
+enum FieldKind {
+    Value,
+    Entity,
+    Resource,
+    Skip
+}
+
+/// Parses `#[proto(...)]` attributes into their nested metas.
+fn proto_metas(attrs: &[Attribute]) -> syn::Result<Vec<NestedMeta>> {
+    let mut ret = vec![];
+    for attr in attrs.iter().filter(|x| x.path.is_ident("proto")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => ret.extend(list.nested),
+            other => return Err(Error::new_spanned(other, "Expected #[proto(...)]"))
+        }
+    }
+    Ok(ret)
+}
+
+/// The struct's `#[proto(...)]` attributes.
+struct ProtoAttrs {
+    name: String,
+    version: u32,
+    schema: bool
+}
+
+fn proto_attrs(input: &DeriveInput) -> syn::Result<ProtoAttrs> {
+    let mut ret = ProtoAttrs {
+        name: input.ident.to_string(),
+        version: 1,
+        schema: false
+    };
+    for meta in proto_metas(&input.attrs)? {
+        match meta {
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("name") => match nv.lit {
+                Lit::Str(s) => ret.name = s.value(),
+                other => return Err(Error::new_spanned(other, "Expected string literal"))
+            },
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("version") => match nv.lit {
+                Lit::Int(i) => ret.version = i.base10_parse()?,
+                other => return Err(Error::new_spanned(other, "Expected integer literal"))
+            },
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident("schema") => ret.schema = true,
+            other => return Err(Error::new_spanned(other,
+                "Unknown proto attribute, expected `name = \"...\"`, `version = N` or `schema`"))
+        }
+    }
+    Ok(ret)
+}
+
+/// Whether the type is spelled `Option<...>`, such fields may be omitted in json.
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(p) => p.qself.is_none() && p.path.segments.last().map(|x| x.ident == "Option").unwrap_or(false),
+        _ => false
+    }
+}
+
+fn field_kind(attrs: &[Attribute]) -> syn::Result<FieldKind> {
+    let mut ret = FieldKind::Value;
+    for meta in proto_metas(attrs)? {
+        let kind = match &meta {
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident("entity") => FieldKind::Entity,
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident("resource") => FieldKind::Resource,
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident("skip") => FieldKind::Skip,
+            other => return Err(Error::new_spanned(other, "Unknown proto attribute, expected `entity`, `resource` or `skip`"))
+        };
+        if let FieldKind::Value = ret {
+            ret = kind;
+        } else {
+            return Err(Error::new_spanned(meta, "Conflicting proto attributes"))
+        }
+    }
+    Ok(ret)
+}
+
+fn impl_proto_component(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(named) => &named.named,
+            _ => return Err(Error::new(Span::call_site(), "ProtoComponent only supports structs with named fields"))
+        },
+        _ => return Err(Error::new(Span::call_site(), "ProtoComponent only supports structs"))
+    };
+
+    let ident = &input.ident;
+    let ProtoAttrs { name, version, schema } = proto_attrs(input)?;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let mut load_fields = vec![];
+    let mut store_fields = vec![];
+    let mut schema_fields = vec![];
+    for field in fields {
+        let field_ident = field.ident.as_ref().unwrap();
+        let key = field_ident.to_string();
+        let ty = &field.ty;
+        let required = !is_option(ty);
+        match field_kind(&field.attrs)? {
+            FieldKind::Value => {
+                load_fields.push(quote! { #field_ident: ctx.read_field(&mut fields, #key)? });
+                store_fields.push(quote! { ctx.write_field(&mut fields, #key, &self.#field_ident)?; });
+                schema_fields.push(quote! { (#key, ::mu::proto::schema_for::<#ty>(), #required) });
+            },
+            FieldKind::Entity => {
+                load_fields.push(quote! { #field_ident: ctx.read_entity(&mut fields, #key)? });
+                store_fields.push(quote! { ctx.write_entity(&mut fields, #key, &self.#field_ident); });
+                schema_fields.push(quote! { (#key, <#ty as ::mu::proto::EntityField>::schema(), #required) });
+            },
+            FieldKind::Resource => {
+                load_fields.push(quote! { #field_ident: ctx.read_resource(&mut fields, #key)? });
+                store_fields.push(quote! { ctx.write_resource(&mut fields, #key, &self.#field_ident)?; });
+                schema_fields.push(quote! { (#key, ::mu::proto::schema_for::<::std::string::String>(), true) });
+            },
+            FieldKind::Skip => {
+                load_fields.push(quote! { #field_ident: ::std::default::Default::default() });
+            }
+        }
+    }
+
+    let schema_fn = if schema {
+        quote! {
+            fn schema() -> ::std::option::Option<::mu::serde_json::Value> {
+                ::std::option::Option::Some(::mu::proto::object_schema(vec![#(#schema_fields),*]))
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::mu::proto::ProtoComponent for #ident #ty_generics #where_clause {
+            const PROTO_NAME: &'static str = #name;
+            const PROTO_VERSION: u32 = #version;
+
+            fn load(data: ::mu::serde_json::Value, ctx: &mut ::mu::proto::ProtoLoadCtx)
+                -> ::std::result::Result<Self, ::mu::proto::ProtoErrorKind> {
+                let mut fields = ::mu::proto::ProtoLoadCtx::fields(data)?;
+                ::std::result::Result::Ok(Self {
+                    #(#load_fields,)*
+                })
+            }
+
+            fn store(&self, ctx: &::mu::proto::ProtoStoreCtx)
+                -> ::std::result::Result<::mu::serde_json::Value, ::mu::proto::ProtoErrorKind> {
+                let mut fields = ::mu::serde_json::Map::new();
+                #(#store_fields)*
+                ::std::result::Result::Ok(::mu::serde_json::Value::Object(fields))
+            }
+
+            #schema_fn
+        }
+    })
+}
@@ -1,5 +1,6 @@
 use std::fs;
 use std::io;
+use std::io::Write;
 use std::path::Path as Path;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::Hasher;
@@ -67,6 +68,19 @@ pub fn set_base_asset_path(path: &'static str) {
     }
 }
 
+/// Writes a temporary file and renames it to `path`, so `path` has either the old or the new content.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
+
 pub fn get_fs_path(path: &str) -> Box<Path> {
     return Path::new(unsafe { BASE_ASSET_PATH }).join(path).into_boxed_path();
 }
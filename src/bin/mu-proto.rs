@@ -0,0 +1,73 @@
+//! Command line tool for proto files.
+//!
+//! ```text
+//! mu-proto convert <input> <output>
+//! mu-proto upgrade <dir>
+//! mu-proto validate <dir>
+//! mu-proto schema <output>
+//! ```
+//!
+//! `convert` selects the format of each file by its extension, see `ProtoFormat::from_path`.
+//! `upgrade` migrates the built-in components of all protos under `dir` in place.
+//! `validate` checks all protos under `dir` against the built-in component schemas.
+//! `schema` writes the JSON Schema of proto files, for editor autocompletion.
+//!
+//! Only the engine's components are known here, see `register_engine_components`. Protos with
+//! other components fail validation, and their data isn't upgraded. A game validates with its
+//! own components from a tool of its own, starting with `ProtoSchemas::builtin` and
+//! `ProtoMigrations::builtin` and adding its registrations through `ComponentS11nRegistry`.
+use std::path::Path;
+use std::process;
+
+use mu::serde_json;
+use mu::proto::{ProtoErrorKind, ProtoFormat, ProtoMigrations, ProtoSchemas};
+
+const USAGE: &str = "Usage: mu-proto convert <input> <output>
+       mu-proto upgrade <dir>
+       mu-proto validate <dir>
+       mu-proto schema <output>";
+
+fn convert(input: &str, output: &str) -> Result<(), ProtoErrorKind> {
+    let bytes = std::fs::read(input)?;
+    let proto = ProtoFormat::from_path(input).decode(&bytes)?;
+    std::fs::write(output, ProtoFormat::from_path(output).encode(&proto)?)?;
+    Ok(())
+}
+
+fn validate(dir: &str) -> Result<(), String> {
+    let errors = ProtoSchemas::builtin().validate_dir(Path::new(dir), &ProtoMigrations::builtin())
+        .map_err(|e| e.to_string())?;
+    for e in &errors {
+        println!("{}", e);
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} problem(s) found", errors.len()))
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.iter().map(|x| x.as_str()).collect::<Vec<_>>().as_slice() {
+        ["convert", input, output] => convert(input, output)
+            .map_err(|e| format!("{}: {}", input, e)),
+        ["upgrade", dir] => ProtoMigrations::builtin().upgrade_dir(Path::new(dir))
+            .map(|changed| changed.iter().for_each(|x| println!("Upgraded {}", x)))
+            .map_err(|errors| {
+                let lines: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                format!("{}\nNo file was changed", lines.join("\n"))
+            }),
+        ["validate", dir] => validate(dir),
+        ["schema", output] => {
+            let schema = serde_json::to_string_pretty(&ProtoSchemas::builtin().combined()).unwrap();
+            std::fs::write(output, schema).map_err(|e| format!("{}: {}", output, e))
+        },
+        _ => Err(USAGE.to_string())
+    };
+
+    if let Err(msg) = result {
+        eprintln!("{}", msg);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,73 @@
+use imgui::*;
+use specs::prelude::*;
+use specs_hierarchy::Hierarchy;
+use crate::client::editor::EditorUIResources;
+use crate::ecs::HasParent;
+use crate::name::{Name, NameIndex, Tags};
+
+pub const VIEW_TOGGLE_ID: &str = "entities";
+
+fn _show_entity(ui: &Ui, entity: Entity, hierarchy: &Hierarchy<HasParent>, index: &NameIndex) {
+    let mut label = index.label(entity);
+    if let Some(tags) = index.tags_of(entity) {
+        if !tags.0.is_empty() {
+            label += &format!("  [{}]", tags.0.join(", "));
+        }
+    }
+
+    let children = hierarchy.children(entity);
+    if children.is_empty() {
+        ui.bullet_text(&im_str!("{}", label));
+        return
+    }
+
+    TreeNode::new(&im_str!("##entity{}", entity.id()))
+        .label(&im_str!("{}", label))
+        .build(ui, || {
+            for child in children {
+                _show_entity(ui, *child, hierarchy, index);
+            }
+        });
+}
+
+/// Lists entities with a `Name` or `Tags` and their descendants, by hierarchy.
+pub(crate) struct EntityViewSystem;
+
+impl<'a> System<'a> for EntityViewSystem {
+    type SystemData = (ReadExpect<'a, EditorUIResources>,
+                       ReadExpect<'a, Hierarchy<HasParent>>,
+                       Read<'a, NameIndex>,
+                       ReadStorage<'a, Name>,
+                       ReadStorage<'a, Tags>,
+                       Entities<'a>);
+
+    fn run(&mut self, (editor_res, hierarchy, index, names, tags, entities): Self::SystemData) {
+        if !editor_res.all_opened_views.contains(VIEW_TOGGLE_ID) {
+            return
+        }
+
+        super::with_frame(|ui| {
+            Window::new(im_str!("Entities"))
+                .size([300., 400.], Condition::FirstUseEver)
+                .build(ui, || {
+                    // Roots of named entities, an unnamed parent is listed with its children
+                    let mut roots: Vec<Entity> = (&entities, &names).join().map(|(e, _)| e)
+                        .chain((&entities, &tags).join().map(|(e, _)| e))
+                        .map(|e| {
+                            let mut root = e;
+                            while let Some(parent) = hierarchy.parent(root) {
+                                root = parent;
+                            }
+                            root
+                        })
+                        .collect();
+                    roots.sort_by_key(|e| e.id());
+                    roots.dedup();
+
+                    for root in roots {
+                        _show_entity(ui, root, &hierarchy, &index);
+                    }
+                });
+        });
+    }
+}
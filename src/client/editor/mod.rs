@@ -13,6 +13,8 @@ use std::collections::HashSet;
 
 pub mod inspect;
 pub mod asset_editor;
+pub mod resource_view;
+pub mod entity_view;
 
 pub const DEP_IMGUI_SETUP: &str = "editor_setup";
 pub const DEP_IMGUI_TEARDOWN: &str = "editor_teardown";
@@ -248,6 +250,8 @@ impl Module for EditorModule {
 
         let mut ui_res = EditorUIResources::new(&mut ctx, &*init_ctx.init_data.world.read_resource());
         ui_res.push_view_toggle(DEMO_WINDOW_TOGGLE, "IMGUI Demo");
+        ui_res.push_view_toggle(resource_view::VIEW_TOGGLE_ID, "Resources");
+        ui_res.push_view_toggle(entity_view::VIEW_TOGGLE_ID, "Entities");
 
         {
             let insert_info = InsertInfo::new(DEP_IMGUI_TEARDOWN).after(&[DEP_IMGUI_SETUP]);
@@ -267,6 +271,17 @@ impl Module for EditorModule {
                 |_, f| f.insert_thread_local(sys));
         }
 
+        init_ctx.init_data.world.insert(resource_view::ResourceViewData::new());
+        init_ctx.group_thread_local.dispatch(
+            InsertInfo::default().after(&[DEP_IMGUI_SETUP]).before(&[DEP_IMGUI_TEARDOWN]),
+            |_, i| i.insert_thread_local(resource_view::ResourceViewSystem)
+        );
+
+        init_ctx.group_thread_local.dispatch(
+            InsertInfo::default().after(&[DEP_IMGUI_SETUP]).before(&[DEP_IMGUI_TEARDOWN]),
+            |_, i| i.insert_thread_local(entity_view::EntityViewSystem)
+        );
+
         if let Some(asset_path) = &self.asset_path {
             ui_res.push_view_toggle(asset_editor::VIEW_TOGGLE_ID, "Assets");
             ui_res.all_opened_views.insert(asset_editor::VIEW_TOGGLE_ID.to_string());
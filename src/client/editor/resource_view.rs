@@ -0,0 +1,123 @@
+use imgui::*;
+use specs::prelude::*;
+use std::collections::HashSet;
+use crate::client::editor::EditorUIResources;
+use crate::resource::{self, ResManager, PoolStats};
+use crate::util::Color;
+
+pub const VIEW_TOGGLE_ID: &str = "resource_stats";
+
+type EntryId = (&'static str, usize, u32);
+
+/// A `Resource` holding the state of the resource statistics view.
+#[derive(Default)]
+pub struct ResourceViewData {
+    /// Resources alive when `mark_scene_change` was last called.
+    snapshot: Option<HashSet<EntryId>>
+}
+
+impl ResourceViewData {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records all currently alive resources. The ones that are still alive afterwards are
+    /// highlighted as possible leaks. Call this when switching scenes.
+    pub fn mark_scene_change(&mut self, res_mgr: &ResManager) {
+        let local_stats = resource::with_local_resource_mgr(|mgr| mgr.stats());
+        let snapshot = res_mgr.stats().iter()
+            .chain(local_stats.iter())
+            .flat_map(|pool| pool.entries.iter().map(move |x| (pool.type_name, x.index, x.generation)))
+            .collect();
+        self.snapshot = Some(snapshot);
+    }
+
+    pub fn clear_mark(&mut self) {
+        self.snapshot = None;
+    }
+
+}
+
+fn _show_pools(ui: &Ui, label: &str, pools: &[PoolStats], snapshot: &Option<HashSet<EntryId>>) {
+    if !CollapsingHeader::new(&im_str!("{}", label)).default_open(true).build(ui) {
+        return
+    }
+
+    let is_leak = |pool: &PoolStats, index: usize, generation: u32| {
+        snapshot.as_ref()
+            .map(|x| x.contains(&(pool.type_name, index, generation)))
+            .unwrap_or(false)
+    };
+
+    for pool in pools {
+        let leak_count = pool.entries.iter()
+            .filter(|x| is_leak(pool, x.index, x.generation))
+            .count();
+        let mut title = format!("{} (live: {}, free: {}, keyed: {})",
+                                pool.type_name, pool.live_count, pool.free_slots, pool.keyed_count);
+        if leak_count > 0 {
+            title += &format!(" [{} kept]", leak_count);
+        }
+
+        TreeNode::new(&im_str!("{}##{}", pool.type_name, label))
+            .label(&im_str!("{}", title))
+            .build(ui, || {
+                for entry in &pool.entries {
+                    let mut text = format!("#{} v{}  refs: {}", entry.index, entry.generation, entry.ref_count);
+                    if let Some(path) = &entry.path {
+                        text += &format!("  {}", path);
+                    }
+                    if !entry.keys.is_empty() {
+                        let keys: Vec<_> = entry.keys.iter().map(|k| format!("{:x}", k)).collect();
+                        text += &format!("  keys: {}", keys.join(", "));
+                    }
+
+                    if is_leak(pool, entry.index, entry.generation) {
+                        ui.text_colored(Color::rgb(1.0, 0.5, 0.3).into(), text);
+                    } else if entry.ref_count == 0 {
+                        ui.text_disabled(text);
+                    } else {
+                        ui.text(text);
+                    }
+                }
+            });
+    }
+}
+
+pub(crate) struct ResourceViewSystem;
+
+impl<'a> System<'a> for ResourceViewSystem {
+    type SystemData = (ReadExpect<'a, EditorUIResources>,
+                       ReadExpect<'a, ResManager>,
+                       WriteExpect<'a, ResourceViewData>);
+
+    fn run(&mut self, (editor_res, res_mgr, mut data): Self::SystemData) {
+        if !editor_res.all_opened_views.contains(VIEW_TOGGLE_ID) {
+            return
+        }
+
+        super::with_frame(|ui| {
+            Window::new(im_str!("Resources"))
+                .size([400., 500.], Condition::FirstUseEver)
+                .build(ui, || {
+                    if ui.small_button(im_str!("Mark scene change")) {
+                        data.mark_scene_change(&res_mgr);
+                    }
+                    if data.snapshot.is_some() {
+                        ui.same_line(0.);
+                        if ui.small_button(im_str!("Clear mark")) {
+                            data.clear_mark();
+                        }
+                    }
+                    ui.separator();
+
+                    let stats = res_mgr.stats();
+                    _show_pools(ui, "Shared", &stats, &data.snapshot);
+
+                    let local_stats = resource::with_local_resource_mgr(|mgr| mgr.stats());
+                    _show_pools(ui, "Thread local", &local_stats, &data.snapshot);
+                });
+        });
+    }
+}
@@ -9,7 +9,7 @@ use crate::{WgpuState};
 use crate::asset::*;
 use crate::resource::*;
 use crate::client::WindowInfo;
-use crate::ecs::Transform;
+use crate::ecs::GlobalTransform;
 use crate::math::{Mat4, Vec3, Vec2};
 use crate::math;
 use crate::Module;
@@ -448,19 +448,32 @@ pub struct Texture {
     pub sampler: wgpu::Sampler
 }
 
+impl ResourceSize for Texture {
+    fn resource_size(&self) -> usize {
+        // All textures are currently created as Rgba8Unorm without mipmaps
+        (self.size.width * self.size.height * self.size.depth * 4) as usize
+    }
+}
+
 pub fn load_texture_raw(path: &str) -> (TextureConfig, DynamicImage) {
-    let config: TextureConfig = load_asset(path).unwrap();
-    let img_bytes: Vec<u8> = load_asset_local(&config._path, &config.image).unwrap();
-    let img = image::load_from_memory_with_format(&img_bytes,
-                                                  image::ImageFormat::Png).unwrap();
-    (config, img)
+    try_load_texture_raw(path).unwrap()
+}
+
+/// Reads and decodes the texture at `path` without touching the GPU, so it can be done off the main thread.
+pub fn try_load_texture_raw(path: &str) -> std::io::Result<(TextureConfig, DynamicImage)> {
+    let config: TextureConfig = load_asset(path)?;
+    let img_bytes: Vec<u8> = load_asset_local(&config._path, &config.image)?;
+    let img = image::load_from_memory_with_format(&img_bytes, image::ImageFormat::Png)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    Ok((config, img))
 }
 
 pub fn load_texture(wgpu_state: &WgpuState, path: &str) -> Texture {
-    let config: TextureConfig = load_asset(path).unwrap();
-    let img_bytes: Vec<u8> = load_asset_local(&config._path, &config.image).unwrap();
-    let img = image::load_from_memory_with_format(&img_bytes,
-                                                  image::ImageFormat::Png).unwrap();
+    let (config, img) = load_texture_raw(path);
+    create_texture_from_image(wgpu_state, &config, img)
+}
+
+pub fn create_texture_from_image(wgpu_state: &WgpuState, config: &TextureConfig, img: DynamicImage) -> Texture {
     let img_dims = img.dimensions();
     create_texture(wgpu_state, img.into_rgba().into_vec(), img_dims, &config.sampler)
 }
@@ -658,7 +671,7 @@ pub struct UniformLayoutConfig {
     pub visibility: UniformVisibility
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum MatProperty {
     Float(f32),
     Vec2(Vec2),
@@ -673,15 +686,20 @@ pub struct Material {
     pub program: ResourceRef<ShaderProgram>,
     pub properties: HashMap<String, MatProperty>,
     bind_group: wgpu::BindGroup,
+    /// Versions of the program and referenced resources when `bind_group` was created.
+    dep_versions: Vec<u32>,
     dirty: bool
 }
 
 impl Material {
 
+    /// Get the bind group, rebuilding it if properties changed or any referenced resource
+    /// has been `replace`d since last build.
     pub fn get_bind_group(&mut self, res_mgr: &ResManager, device: &wgpu::Device) -> &wgpu::BindGroup {
-        if self.dirty {
+        if self.dirty || !self.dep_versions.iter().copied().eq(Self::dep_versions(res_mgr, &self.program, &self.properties)) {
             let program = res_mgr.get(&self.program);
             self.bind_group = Self::create_bind_group(res_mgr, program, device, &self.properties);
+            self.dep_versions = Self::dep_versions(res_mgr, &self.program, &self.properties).collect();
             self.dirty = false;
         }
 
@@ -689,15 +707,30 @@ impl Material {
     }
 
     pub fn set(&mut self, name: &str, p: MatProperty) {
-        assert!(self.properties.contains_key(name), "Can't add non-existent property");
-        self.properties.insert(name.to_string(), p);
-        self.mark_dirty();
+        let prev = self.properties.get_mut(name).expect("Can't add non-existent property");
+        if *prev != p {
+            *prev = p;
+            self.mark_dirty();
+        }
     }
 
     fn mark_dirty(&mut self) {
         self.dirty = true;
     }
 
+    fn dep_versions<'a>(
+        res_mgr: &'a ResManager,
+        program: &ResourceRef<ShaderProgram>,
+        dict: &'a HashMap<String, MatProperty>) -> impl Iterator<Item=u32> + 'a {
+        let props = dict.values()
+            .filter_map(move |p| match p {
+                MatProperty::Texture(tex) | MatProperty::TextureSampler(tex) => Some(res_mgr.version(tex)),
+                MatProperty::Sampler(smp) => Some(res_mgr.version(smp)),
+                _ => None
+            });
+        std::iter::once(res_mgr.version(program)).chain(props)
+    }
+
     fn create_bind_group<'a>(
         res_mgr: &'a ResManager,
         program: &ShaderProgram,
@@ -860,10 +893,12 @@ impl Material {
         properties: HashMap<String, MatProperty>) -> Self {
         let shader_program = res_mgr.get(&program);
         let bind_group = Self::create_bind_group(res_mgr, &shader_program, &wgpu_states.device, &properties);
+        let dep_versions = Self::dep_versions(res_mgr, &program, &properties).collect();
         Self {
             program,
             properties,
             bind_group,
+            dep_versions,
             dirty: false
         }
     }
@@ -891,7 +926,7 @@ mod internal {
 
     impl<'a> System<'a> for SysRenderPrepare {
         type SystemData = (ReadExpect<'a, WindowInfo>, ReadExpect<'a, WgpuState>,
-                           Entities<'a>, WriteStorage<'a, Camera>, ReadStorage<'a, Transform>);
+                           Entities<'a>, WriteStorage<'a, Camera>, ReadStorage<'a, GlobalTransform>);
 
         fn run(&mut self, (window_info, wgpu_state, entities, mut cameras, transforms): Self::SystemData) {
             // let mut frame = self.display.draw();
@@ -934,8 +969,7 @@ mod internal {
                     }
                 };
 
-                let rot = Mat4::from_quat(trans.rot.conjugate());
-                let world_view: Mat4 = rot * math::Mat4::from_translation(-trans.pos);
+                let world_view: Mat4 = trans.0.inverse();
 
                 let mut encoder = wgpu_state.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
                     label: Some(&format!("Camera {}", cam_id)),
@@ -979,7 +1013,7 @@ mod internal {
 
                 let cam_render_data = CamRenderData {
                     wvp_matrix,
-                    world_pos: trans.pos,
+                    world_pos: trans.position(),
                     encoder,
                     entity: ent,
                     depth_texture_view
@@ -1016,11 +1050,15 @@ mod internal {
     }
 }
 
+/// Bytes of unreferenced textures kept in memory, so reloading a sheet using them skips the upload.
+pub const TEXTURE_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
 pub struct GraphicsModule;
 
 impl Module for GraphicsModule {
     fn init(&self, init_data: &mut crate::InitContext) {
         use crate::InsertInfo;
+        init_data.init_data.res_mgr.set_retention_policy::<Texture>(RetentionPolicy::lru_bytes(TEXTURE_CACHE_BYTES));
         {
             init_data.dispatch_thread_local(
                 InsertInfo::new(DEP_CAM_DRAW_SETUP)
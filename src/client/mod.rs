@@ -6,6 +6,7 @@ pub mod sprite;
 pub mod editor;
 pub mod ui;
 pub mod text;
+pub mod tween;
 
 /// A specs `Resource`. contains information about window.
 pub struct WindowInfo {
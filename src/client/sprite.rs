@@ -1,9 +1,8 @@
 use std::collections::HashMap;
 use std::io;
-use std::pin::Pin;
 
-use futures::Future;
 use imgui_inspect_derive::Inspect;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use serde_json::Value;
@@ -16,10 +15,10 @@ use crate::asset::*;
 use crate::client::editor::asset_editor::AssetInspectorResources;
 use crate::client::graphics::*;
 use crate::client::graphics;
-use crate::ecs::Transform;
+use crate::ecs::GlobalTransform;
 use crate::math::*;
 use crate::proto::*;
-use crate::resource::{ResManager, ResourceRef};
+use crate::resource::{ResManager, ResourceRef, ResourceSize, RetentionPolicy};
 use crate::util::Color;
 
 use super::editor::inspect::*;
@@ -89,8 +88,7 @@ pub struct Sprite {
 pub struct SpriteSheet {
     pub sprites: Vec<Sprite>,
     pub texture: ResourceRef<Texture>,
-    pub ppu: u32,
-    pub path: Option<String>
+    pub ppu: u32
 }
 
 impl SpriteSheet {
@@ -99,6 +97,13 @@ impl SpriteSheet {
     }
 }
 
+impl ResourceSize for SpriteSheet {
+    /// Doesn't include the texture, which lives in its own pool.
+    fn resource_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.sprites.len() * std::mem::size_of::<Sprite>()
+    }
+}
+
 // 由于texture不能放到Component里（无法跨线程），且太重量级，在加载完后以及Component层使用SpriteRef
 // 在渲染时才由SpriteRef拿回Sprite，利用Texture进行实际绘制
 
@@ -108,7 +113,7 @@ pub struct SpriteRef {
     pub idx: usize,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct SpriteRefS11nData {
     pub sheet: String,
     pub idx: usize
@@ -136,62 +141,135 @@ pub struct SpriteRefS11n;
 type SpriteRefS11nSystemData<'a> = (WriteExpect<'a, ResManager>, ReadExpect<'a, WgpuState>);
 type SpriteRefS11nStoreSystemData<'a> = ReadExpect<'a, ResManager>;
 
+/// A `SpriteRef` being loaded by `SpriteRefS11n`.
+pub(crate) struct SpriteRefLoaded {
+    sheet: SpriteSheetLoaded,
+    idx: usize
+}
+
+enum SpriteSheetLoaded {
+    Cached(ResourceRef<SpriteSheet>),
+    Read(String, SpriteSheetData)
+}
+
 impl SpriteRefS11n {
 
-    fn load(&mut self, data: Value, (res_mgr, wgpu_state): &mut SpriteRefS11nSystemData) -> SpriteRef {
-        let s11n: SpriteRefS11nData = serde_json::from_value(data).unwrap();
-        let sheet = load_sprite_sheet(&mut *res_mgr, &*wgpu_state, &s11n.sheet).unwrap();
-        SpriteRef::new(&sheet, s11n.idx)
+    /// Looks up the sheet if it's already loaded, otherwise returns a future reading it from disk.
+    fn load_async(&mut self, data: Value, progress: &ProtoLoadProgress, (res_mgr, _): &mut SpriteRefS11nSystemData)
+        -> Result<ComponentLoadFuture<SpriteRefLoaded>, ProtoErrorKind> {
+        let s11n: SpriteRefS11nData = serde_json::from_value(data)?;
+        if let Some(sheet) = res_mgr.get_by_path(&s11n.sheet) {
+            return Ok(Box::pin(async move {
+                Ok(SpriteRefLoaded { sheet: SpriteSheetLoaded::Cached(sheet), idx: s11n.idx })
+            }))
+        }
+
+        let ticket = progress.start_asset();
+        Ok(Box::pin(async move {
+            // Completes the asset in the progress when the read is done
+            let _ticket = ticket;
+            let data = read_sprite_sheet(&s11n.sheet)?;
+            Ok(SpriteRefLoaded { sheet: SpriteSheetLoaded::Read(s11n.sheet, data), idx: s11n.idx })
+        }))
     }
 
-    fn store(&mut self, sprite_ref: &SpriteRef, res_mgr: &mut SpriteRefS11nStoreSystemData) -> Value {
-        let sheet = res_mgr.get(&sprite_ref.sheet);
-        let sheet_path = sheet.path.clone().expect("No SpriteSheet path");
+    fn integrate(&mut self, loaded: SpriteRefLoaded, (res_mgr, wgpu_state): &mut SpriteRefS11nSystemData) -> Result<SpriteRef, ProtoErrorKind> {
+        let sheet = match loaded.sheet {
+            SpriteSheetLoaded::Cached(sheet) => sheet,
+            SpriteSheetLoaded::Read(path, data) => match res_mgr.get_by_path(&path) {
+                // Loaded by another entity in the meantime
+                Some(sheet) => sheet,
+                None => {
+                    let sheet = create_sprite_sheet(&mut *res_mgr, &*wgpu_state, data);
+                    res_mgr.add_with_path(sheet, &path)
+                }
+            }
+        };
+
+        if loaded.idx >= res_mgr.get(&sheet).sprites.len() {
+            return Err(ProtoErrorKind::Invalid(format!("Sprite index {} out of range in {}",
+                                                       loaded.idx, res_mgr.path_of(&sheet).unwrap_or("sheet"))))
+        }
+        Ok(SpriteRef::new(&sheet, loaded.idx))
+    }
+
+    /// `None` if the sheet isn't loaded from a path, e.g. created with `ResManager::add`.
+    fn store(&mut self, sprite_ref: &SpriteRef, res_mgr: &mut SpriteRefS11nStoreSystemData) -> Option<Value> {
+        let sheet_path = res_mgr.path_of(&sprite_ref.sheet)?.to_string();
 
         let s11n = SpriteRefS11nData {
             sheet: sheet_path,
             idx: sprite_ref.idx
         };
 
-        serde_json::to_value(s11n).unwrap()
+        Some(serde_json::to_value(s11n).unwrap())
     }
 
 }
 
+/// Sprite sheet read from disk, but not yet uploaded to the GPU.
+pub struct SpriteSheetData {
+    config: SpriteSheetConfig,
+    texture_path: String,
+    texture_config: TextureConfig,
+    image: image::DynamicImage
+}
+
+/// Reads the sprite sheet at `path` and decodes its texture. Doesn't need the main thread.
+pub fn read_sprite_sheet(path: &str) -> io::Result<SpriteSheetData> {
+    let config: SpriteSheetConfig = asset::load_asset(path)?;
+    let texture_path = asset::get_asset_path_local(&config._path, &config.texture);
+    let (texture_config, image) = graphics::try_load_texture_raw(&texture_path)?;
+    Ok(SpriteSheetData {
+        config,
+        texture_path,
+        texture_config,
+        image
+    })
+}
+
 pub fn load_sprite_sheet(res_mgr: &mut ResManager, wgpu_state: &WgpuState, path: &str) -> io::Result<ResourceRef<SpriteSheet>> {
-    let key = get_path_hash(path);
-    if let Some(ret) = res_mgr.get_by_key(key) {
+    if let Some(ret) = res_mgr.get_by_path(path) {
         Ok(ret)
     } else {
-        let config: SpriteSheetConfig = asset::load_asset(path)?;
-        let texture = graphics::load_texture(wgpu_state,
-                                             &asset::get_asset_path_local(&config._path, &config.texture));
-        let (tex_width, tex_height) = (texture.size.width as f32, texture.size.height as f32);
-
-        let sprites: Vec<Sprite> = (&config.sprites).into_iter()
-            .map(|x| {
-                let pos_f32 = x.pos_f32();
-                let size_f32 = x.size_f32();
-                let tuv1: Vec2 = pos_f32 - size_f32 * 0.5;
-                let tuv2: Vec2 = pos_f32 + size_f32 * 0.5;
-
-                let u1 = tuv1.x / tex_width;
-                let v1 = tuv2.y / tex_height;
-                let u2 = tuv2.x / tex_width;
-                let v2 = tuv1.y / tex_height;
-
-                Sprite { config: x.clone(), uv_min: vec2(u1, v1), uv_max: vec2(u2, v2) }
-            })
-            .collect();
+        let sheet = create_sprite_sheet(res_mgr, wgpu_state, read_sprite_sheet(path)?);
+        Ok(res_mgr.add_with_path(sheet, path))
+    }
+}
 
-        let sheet = SpriteSheet {
-            texture: res_mgr.add(texture),
-            sprites,
-            ppu: config.ppu,
-            path: Some(path.to_string())
-        };
+pub fn create_sprite_sheet(res_mgr: &mut ResManager, wgpu_state: &WgpuState, data: SpriteSheetData) -> SpriteSheet {
+    let SpriteSheetData { config, texture_path, texture_config, image } = data;
+    // Sheets sharing a texture, or a texture kept by its retention policy, skip the upload
+    let texture = match res_mgr.get_by_path::<Texture>(&texture_path) {
+        Some(texture) => texture,
+        None => {
+            let texture = graphics::create_texture_from_image(wgpu_state, &texture_config, image);
+            res_mgr.add_with_path(texture, &texture_path)
+        }
+    };
+    let size = res_mgr.get(&texture).size;
+    let (tex_width, tex_height) = (size.width as f32, size.height as f32);
+
+    let sprites: Vec<Sprite> = config.sprites.iter()
+        .map(|x| {
+            let pos_f32 = x.pos_f32();
+            let size_f32 = x.size_f32();
+            let tuv1: Vec2 = pos_f32 - size_f32 * 0.5;
+            let tuv2: Vec2 = pos_f32 + size_f32 * 0.5;
+
+            let u1 = tuv1.x / tex_width;
+            let v1 = tuv2.y / tex_height;
+            let u2 = tuv2.x / tex_width;
+            let v2 = tuv1.y / tex_height;
+
+            Sprite { config: x.clone(), uv_min: vec2(u1, v1), uv_max: vec2(u2, v2) }
+        })
+        .collect();
 
-        Ok(res_mgr.add_by_key(sheet, key))
+    SpriteSheet {
+        texture,
+        sprites,
+        ppu: config.ppu
     }
 }
 
@@ -218,44 +296,70 @@ impl Component for SpriteRenderer {
 }
 
 #[derive(Clone)]
-struct SpriteRendererS11n;
+pub(crate) struct SpriteRendererS11n;
 
 impl<'a> ComponentS11n<'a> for SpriteRendererS11n {
     type SystemData = SpriteRefS11nSystemData<'a>;
     type StoreSystemData = SpriteRefS11nStoreSystemData<'a>;
     type Output = SpriteRenderer;
+    type Loaded = (SpriteRefLoaded, Color);
 
-    fn load_async(&mut self, mut ctx: ComponentLoadArgs, system_data: &mut Self::SystemData) -> Pin<Box<dyn Future<Output=Self::Output> + Send + Sync>> {
-        let color: Color = serde_json::from_value(ctx.data["color"].take()).unwrap();
-        let sprite_ref = SpriteRefS11n.load(ctx.data["sprite"].take(), system_data);
+    fn load_async(&mut self, mut ctx: ComponentLoadArgs, system_data: &mut Self::SystemData) -> Result<ComponentLoadFuture<Self::Loaded>, ProtoErrorKind> {
+        let color: Color = serde_json::from_value(ctx.data["color"].take())?;
+        let sprite_fut = SpriteRefS11n.load_async(ctx.data["sprite"].take(), ctx.progress, system_data)?;
 
-        Box::pin(async move {
-            SpriteRenderer {
-                sprite: sprite_ref,
-                material: None,
-                color
-            }
+        Ok(Box::pin(async move {
+            Ok((sprite_fut.await?, color))
+        }))
+    }
+
+    fn integrate(&mut self, (sprite, color): Self::Loaded, system_data: &mut Self::SystemData) -> Result<Self::Output, ProtoErrorKind> {
+        Ok(SpriteRenderer {
+            sprite: SpriteRefS11n.integrate(sprite, system_data)?,
+            material: None,
+            color
         })
     }
 
-    fn store(&mut self, ctx: ComponentStoreArgs<Self::Output>, system_data: &mut Self::StoreSystemData) -> Value {
+    fn store(&mut self, ctx: ComponentStoreArgs<Self::Output>, system_data: &mut Self::StoreSystemData) -> Option<Value> {
         let color_value = serde_json::to_value(ctx.component.color).unwrap();
-        let sprite_ref_value = SpriteRefS11n.store(&ctx.component.sprite, system_data);
-        serde_json::json!({
+        let sprite_ref_value = match SpriteRefS11n.store(&ctx.component.sprite, system_data) {
+            Some(x) => x,
+            None => {
+                warn!("SpriteRenderer uses a sheet that isn't loaded from path, omitting it");
+                return None
+            }
+        };
+        Some(serde_json::json!({
             "color": color_value,
             "sprite": sprite_ref_value
-        })
+        }))
     }
 
     fn type_name(&self) -> &'static str {
         "SpriteRenderer"
     }
+
+    fn schema(&self) -> Option<Value> {
+        #[derive(JsonSchema)]
+        #[allow(dead_code)]
+        struct SpriteRendererSchema {
+            color: Color,
+            sprite: SpriteRefS11nData
+        }
+        Some(schema_for::<SpriteRendererSchema>())
+    }
 }
 
+/// Unreferenced sprite sheets kept loaded, so respawning sprites doesn't read them again.
+pub const SPRITE_SHEET_CACHE_COUNT: usize = 16;
+
 pub struct SpriteModule;
 
 impl Module for SpriteModule {
     fn init(&self, ctx: &mut InitContext) {
+        // Retained sheets keep their texture referenced, so this bounds textures too
+        ctx.init_data.res_mgr.set_retention_policy::<SpriteSheet>(RetentionPolicy::lru_count(SPRITE_SHEET_CACHE_COUNT));
         ctx.add_component_s11n(SpriteRendererS11n);
 
         ctx.dispatch_thread_local(
@@ -531,15 +635,17 @@ struct Batch {
 }
 
 impl<'a> System<'a> for SpriteRenderSystem {
-    type SystemData = (ReadExpect<'a, WgpuState>, ReadExpect<'a, ResManager>, ReadStorage<'a, SpriteRenderer>, ReadStorage<'a, Transform>);
+    type SystemData = (ReadExpect<'a, WgpuState>, ReadExpect<'a, ResManager>, ReadStorage<'a, SpriteRenderer>, ReadStorage<'a, GlobalTransform>);
 
     fn run(&mut self, (wgpu_state, sprite_mgr, sr_vec, trans_vec): Self::SystemData) {
         let mut cur_batch: Option<Batch> = None;
-        for (trans, sr) in (&trans_vec, &sr_vec).join() {
-            let world_view = Mat4::from_translation(trans.pos) * Mat4::from_quat(trans.rot);
+        // There's no depth test, draw back to front. Sorting is stable to keep batches of equal depth
+        let mut sorted: Vec<(&GlobalTransform, &SpriteRenderer)> = (&trans_vec, &sr_vec).join().collect();
+        sorted.sort_by(|a, b| a.0.position().z.partial_cmp(&b.0.position().z).unwrap_or(std::cmp::Ordering::Equal));
+        for (trans, sr) in sorted {
             let sprite_instance = SpriteInstance {
                 idx: sr.sprite.idx,
-                world_view,
+                world_view: trans.0,
                 color: sr.color.clone()
             };
             // Batching
@@ -5,7 +5,7 @@ use crate::*;
 use crate::math::*;
 use crate::client::graphics::*;
 use crate::util::Color;
-use crate::ecs::Transform;
+use crate::ecs::GlobalTransform;
 use crate::asset::*;
 use std::io::{Error, ErrorKind};
 
@@ -60,7 +60,7 @@ mod internal {
     }
 
     impl<'a> System<'a> for WorldTextRenderSystem {
-        type SystemData = (ReadExpect<'a, WgpuState>, WriteExpect<'a, FontRuntimeData>, ReadStorage<'a, WorldText>, ReadStorage<'a, Transform>);
+        type SystemData = (ReadExpect<'a, WgpuState>, WriteExpect<'a, FontRuntimeData>, ReadStorage<'a, WorldText>, ReadStorage<'a, GlobalTransform>);
 
         fn run(&mut self, (wgpu_state, mut font_data, world_text_read, transform_read): Self::SystemData) {
             let font_data_ref = &mut *font_data;
@@ -82,7 +82,7 @@ mod internal {
 
                         let scl = text.sz / size_scl;
                         let scl_mat = Mat4::from_scale(vec3(scl, -scl, 1.));
-                        let wvp_mat = cam.wvp_matrix * trans.get_world_view() * scl_mat;
+                        let wvp_mat = cam.wvp_matrix * trans.0 * scl_mat;
 
                         font_data_ref.glyph_brush.draw_queued_with_transform(&wgpu_state.device, &mut rd.staging_belt, &mut cam.encoder,
                                                                              &wgpu_state.frame_texture.as_ref().unwrap().output.view, wvp_mat.to_cols_array())
@@ -0,0 +1,457 @@
+//! Interpolating component properties over time.
+//!
+//! A `Tween` plays its steps one after another, each with its own delay, duration and easing.
+//! All steps together make a pass, which can be repeated and played backwards every other time
+//! with `ping_pong`. The tween is removed from the entity when done, firing `TweenEvent::Completed`.
+//! Tweens only run with `TweenModule` added to the game.
+//!
+//! ```ignore
+//! let tween = Tween::new(TweenStep::new(TweenTarget::ImageColor(Color::white(), Color::mono(0.8)), 0.1))
+//!     .then(TweenStep::new(TweenTarget::WidgetScale(vec2(1., 1.), vec2(1.2, 1.2)), 0.2).ease(Ease::BackOut))
+//!     .ping_pong()
+//!     .loops(TweenLoops::Count(2));
+//! ```
+use specs::prelude::*;
+
+use crate::{InitContext, InsertInfo, Module};
+use crate::client::sprite::SpriteRenderer;
+use crate::client::ui::{Image, LayoutType, UIText, Widget};
+use crate::ecs::{Time, Transform};
+use crate::math::*;
+use crate::util::Color;
+
+pub static DEP_TWEEN: &str = "tween";
+
+/// Easing functions, see [easings.net](https://easings.net).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Ease {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineIn,
+    SineOut,
+    SineInOut,
+    /// Overshoots the end a little, then settles.
+    BackOut,
+    ElasticOut,
+    BounceOut
+}
+
+impl Ease {
+
+    /// Maps progress `t` in [0, 1] to the interpolation factor. Starts at 0 and ends at 1, but
+    /// may leave [0, 1] in between.
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Ease::Linear => t,
+            Ease::QuadIn => t * t,
+            Ease::QuadOut => 1. - (1. - t) * (1. - t),
+            Ease::QuadInOut => if t < 0.5 {
+                2. * t * t
+            } else {
+                1. - (-2. * t + 2.).powi(2) / 2.
+            },
+            Ease::CubicIn => t * t * t,
+            Ease::CubicOut => 1. - (1. - t).powi(3),
+            Ease::CubicInOut => if t < 0.5 {
+                4. * t * t * t
+            } else {
+                1. - (-2. * t + 2.).powi(3) / 2.
+            },
+            Ease::SineIn => 1. - (t * PI / 2.).cos(),
+            Ease::SineOut => (t * PI / 2.).sin(),
+            Ease::SineInOut => -((PI * t).cos() - 1.) / 2.,
+            Ease::BackOut => {
+                const C1: f32 = 1.70158;
+                const C3: f32 = C1 + 1.;
+                1. + C3 * (t - 1.).powi(3) + C1 * (t - 1.).powi(2)
+            },
+            Ease::ElasticOut => if t <= 0. || t >= 1. {
+                t
+            } else {
+                2f32.powf(-10. * t) * ((t * 10. - 0.75) * (2. * PI / 3.)).sin() + 1.
+            },
+            Ease::BounceOut => {
+                const N1: f32 = 7.5625;
+                const D1: f32 = 2.75;
+                if t < 1. / D1 {
+                    N1 * t * t
+                } else if t < 2. / D1 {
+                    let t = t - 1.5 / D1;
+                    N1 * t * t + 0.75
+                } else if t < 2.5 / D1 {
+                    let t = t - 2.25 / D1;
+                    N1 * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / D1;
+                    N1 * t * t + 0.984375
+                }
+            }
+        }
+    }
+
+}
+
+/// A property and the values it's interpolated between.
+#[derive(Copy, Clone, Debug)]
+pub enum TweenTarget {
+    /// `Transform.pos`
+    Position(Vec3, Vec3),
+    /// `Transform.rot`, spherically interpolated.
+    Rotation(Quat, Quat),
+    /// `Transform.scale`
+    Scale(Vec3, Vec3),
+    /// `SpriteRenderer.color`
+    SpriteColor(Color, Color),
+    /// `Image.color`
+    ImageColor(Color, Color),
+    /// `UIText.color`
+    TextColor(Color, Color),
+    /// `Widget.scl`
+    WidgetScale(Vec2, Vec2),
+    /// `Widget.rot`
+    WidgetRot(f32, f32),
+    /// `Widget.pivot`
+    WidgetPivot(Vec2, Vec2),
+    /// `pos` of the widget's `LayoutType::Normal` layouts, expanded axes are left alone.
+    WidgetPos(Vec2, Vec2)
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TweenStep {
+    pub target: TweenTarget,
+    /// Time before the step starts, after the previous step ended.
+    pub delay: f32,
+    pub duration: f32,
+    pub ease: Ease
+}
+
+impl TweenStep {
+
+    pub fn new(target: TweenTarget, duration: f32) -> Self {
+        Self {
+            target,
+            delay: 0.,
+            duration,
+            ease: Ease::Linear
+        }
+    }
+
+    pub fn ease(mut self, ease: Ease) -> Self {
+        self.ease = ease;
+        self
+    }
+
+    pub fn delay(mut self, delay: f32) -> Self {
+        self.delay = delay;
+        self
+    }
+
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TweenLoops {
+    /// Number of passes. With `ping_pong`, there and back again is 2.
+    Count(u32),
+    Infinite
+}
+
+pub struct Tween {
+    pub steps: Vec<TweenStep>,
+    pub loops: TweenLoops,
+    /// Whether every second pass plays backwards.
+    pub ping_pong: bool,
+    /// Time since the tween started, delays included.
+    elapsed: f32
+}
+
+impl Component for Tween {
+    type Storage = DenseVecStorage<Self>;
+}
+
+impl Tween {
+
+    pub fn new(step: TweenStep) -> Self {
+        Self {
+            steps: vec![step],
+            loops: TweenLoops::Count(1),
+            ping_pong: false,
+            elapsed: 0.
+        }
+    }
+
+    /// Appends a step starting after the previous one ended.
+    pub fn then(mut self, step: TweenStep) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn loops(mut self, loops: TweenLoops) -> Self {
+        self.loops = loops;
+        self
+    }
+
+    pub fn ping_pong(mut self) -> Self {
+        self.ping_pong = true;
+        self
+    }
+
+    /// Duration of one pass.
+    pub fn pass_duration(&self) -> f32 {
+        self.steps.iter().map(|x| x.delay + x.duration).sum()
+    }
+
+    /// Index of the current pass and the time into it, played backwards if it's reversed.
+    fn pass_time(&self) -> (u32, f32) {
+        let len = self.pass_duration();
+        if len <= 0. {
+            return (0, 0.)
+        }
+
+        let mut pass = (self.elapsed / len).floor() as u32;
+        let mut t = self.elapsed - pass as f32 * len;
+        if let TweenLoops::Count(n) = self.loops {
+            // Hold the end of the last pass
+            if pass >= n {
+                pass = n.max(1) - 1;
+                t = len;
+            }
+        }
+
+        if self.ping_pong && pass % 2 == 1 {
+            (pass, len - t)
+        } else {
+            (pass, t)
+        }
+    }
+
+    /// Progress of every step at pass time `t`, ordered so that for each property the step
+    /// closest to `t` is applied last: not yet started steps in reverse, ended steps, then
+    /// the running one.
+    fn sample(&self, t: f32) -> Vec<(&TweenStep, f32)> {
+        let mut start = 0.;
+        let progress: Vec<(&TweenStep, f32)> = self.steps.iter()
+            .map(|step| {
+                start += step.delay;
+                let p = if step.duration > 0. {
+                    clamp((t - start) / step.duration, 0., 1.)
+                } else if t >= start {
+                    1.
+                } else {
+                    0.
+                };
+                start += step.duration;
+                (step, p)
+            })
+            .collect();
+
+        let not_started = progress.iter().rev().filter(|(_, p)| *p <= 0.);
+        let ended = progress.iter().filter(|(_, p)| *p >= 1.);
+        let running = progress.iter().filter(|(_, p)| *p > 0. && *p < 1.);
+        not_started.chain(ended).chain(running)
+            .map(|(step, p)| (*step, step.ease.apply(*p)))
+            .collect()
+    }
+
+    /// Whether the last pass has ended. Never true for `TweenLoops::Infinite`.
+    pub fn is_finished(&self) -> bool {
+        match self.loops {
+            TweenLoops::Count(n) => self.elapsed >= n as f32 * self.pass_duration(),
+            TweenLoops::Infinite => false
+        }
+    }
+
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TweenEvent {
+    /// A pass other than the last one ended. `pass` counts from 0.
+    LoopCompleted { entity: Entity, pass: u32 },
+    /// The last pass ended, the `Tween` is removed.
+    Completed { entity: Entity }
+}
+
+/// A specs `Resource`. Contains tween events of the last `TweenSystem` run.
+#[derive(Default)]
+pub struct TweenEvents {
+    pub events: Vec<TweenEvent>
+}
+
+#[derive(SystemData)]
+pub struct TweenData<'a> {
+    entities: Entities<'a>,
+    tweens: WriteStorage<'a, Tween>,
+    transforms: WriteStorage<'a, Transform>,
+    sprites: WriteStorage<'a, SpriteRenderer>,
+    images: WriteStorage<'a, Image>,
+    texts: WriteStorage<'a, UIText>,
+    widgets: WriteStorage<'a, Widget>,
+    events: Write<'a, TweenEvents>
+}
+
+impl<'a> TweenData<'a> {
+
+    /// Advances all tweens by `dt` seconds and applies them. Targets the entity doesn't have
+    /// are skipped.
+    pub fn update(&mut self, dt: f32) {
+        self.events.events.clear();
+
+        let mut finished = vec![];
+        // Applied after the join, which borrows the tweens
+        let mut samples = vec![];
+        for (entity, tween) in (&self.entities, &mut self.tweens).join() {
+            let (last_pass, _) = tween.pass_time();
+            tween.elapsed += dt;
+            let (pass, t) = tween.pass_time();
+            for p in last_pass..pass {
+                self.events.events.push(TweenEvent::LoopCompleted { entity, pass: p });
+            }
+
+            samples.extend(tween.sample(t).into_iter().map(|(step, f)| (entity, step.target, f)));
+
+            if tween.is_finished() {
+                finished.push(entity);
+            }
+        }
+
+        for (entity, target, f) in samples {
+            self.apply_target(target, f, entity);
+        }
+
+        for entity in finished {
+            self.tweens.remove(entity);
+            self.events.events.push(TweenEvent::Completed { entity });
+        }
+    }
+
+    fn apply_target(&mut self, target: TweenTarget, f: f32, entity: Entity) {
+        match target {
+            TweenTarget::Position(from, to) => if let Some(x) = self.transforms.get_mut(entity) {
+                x.pos = from.lerp(to, f);
+            },
+            TweenTarget::Rotation(from, to) => if let Some(x) = self.transforms.get_mut(entity) {
+                x.rot = from.slerp(to, f);
+            },
+            TweenTarget::Scale(from, to) => if let Some(x) = self.transforms.get_mut(entity) {
+                x.scale = from.lerp(to, f);
+            },
+            TweenTarget::SpriteColor(from, to) => if let Some(x) = self.sprites.get_mut(entity) {
+                x.color = Color::lerp(&from, &to, f);
+            },
+            TweenTarget::ImageColor(from, to) => if let Some(x) = self.images.get_mut(entity) {
+                x.color = Color::lerp(&from, &to, f);
+            },
+            TweenTarget::TextColor(from, to) => if let Some(x) = self.texts.get_mut(entity) {
+                x.color = Color::lerp(&from, &to, f);
+            },
+            TweenTarget::WidgetScale(from, to) => if let Some(x) = self.widgets.get_mut(entity) {
+                x.scl = from.lerp(to, f);
+                x._mark_dirty();
+            },
+            TweenTarget::WidgetRot(from, to) => if let Some(x) = self.widgets.get_mut(entity) {
+                x.rot = lerp(from, to, f);
+                x._mark_dirty();
+            },
+            TweenTarget::WidgetPivot(from, to) => if let Some(x) = self.widgets.get_mut(entity) {
+                x.pivot = from.lerp(to, f);
+                x._mark_dirty();
+            },
+            TweenTarget::WidgetPos(from, to) => if let Some(x) = self.widgets.get_mut(entity) {
+                let pos = from.lerp(to, f);
+                if let LayoutType::Normal { pos: ref mut px, .. } = x.layout_x {
+                    *px = pos.x;
+                }
+                if let LayoutType::Normal { pos: ref mut py, .. } = x.layout_y {
+                    *py = pos.y;
+                }
+                x._mark_dirty();
+            }
+        }
+    }
+
+}
+
+struct TweenSystem;
+
+impl<'a> System<'a> for TweenSystem {
+    type SystemData = (ReadExpect<'a, Time>, TweenData<'a>);
+
+    fn run(&mut self, (time, mut data): Self::SystemData) {
+        data.update(time.get_delta_time());
+    }
+}
+
+pub struct TweenModule;
+
+impl Module for TweenModule {
+    fn init(&self, init_ctx: &mut InitContext) {
+        init_ctx.init_data.world.insert(TweenEvents::default());
+        init_ctx.dispatch(InsertInfo::new(DEP_TWEEN), |_, i| i.insert(TweenSystem));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sequence_ping_pong() {
+        let mut world = World::new();
+        world.insert(TweenEvents::default());
+        world.register::<Tween>();
+        world.register::<Transform>();
+        world.register::<SpriteRenderer>();
+        world.register::<Image>();
+        world.register::<UIText>();
+        world.register::<Widget>();
+
+        let tween = Tween::new(TweenStep::new(TweenTarget::Position(vec3(0., 0., 0.), vec3(4., 0., 0.)), 1.))
+            .then(TweenStep::new(TweenTarget::Position(vec3(4., 0., 0.), vec3(4., 2., 0.)), 1.).delay(1.))
+            .ping_pong()
+            .loops(TweenLoops::Count(2));
+        let e = world.create_entity()
+            .with(Transform::new())
+            .with(tween)
+            .build();
+
+        let step = |world: &mut World, dt: f32| {
+            world.system_data::<TweenData>().update(dt);
+            let pos = world.read_storage::<Transform>().get(e).unwrap().pos;
+            let events = world.read_resource::<TweenEvents>().events.clone();
+            (pos, events)
+        };
+
+        assert_eq!(step(&mut world, 0.5).0, vec3(2., 0., 0.));
+        // The delay holds the end of the first step
+        assert_eq!(step(&mut world, 1.).0, vec3(4., 0., 0.));
+        assert_eq!(step(&mut world, 1.).0, vec3(4., 1., 0.));
+
+        // Backwards in the second pass
+        let (pos, events) = step(&mut world, 1.);
+        assert_eq!(pos, vec3(4., 1., 0.));
+        assert_eq!(events, vec![TweenEvent::LoopCompleted { entity: e, pass: 0 }]);
+        assert_eq!(step(&mut world, 1.).0, vec3(4., 0., 0.));
+        assert_eq!(step(&mut world, 1.).0, vec3(2., 0., 0.));
+
+        let (pos, events) = step(&mut world, 1.);
+        assert_eq!(pos, vec3(0., 0., 0.));
+        assert_eq!(events, vec![TweenEvent::Completed { entity: e }]);
+        assert!(world.read_storage::<Tween>().get(e).is_none());
+    }
+
+    #[test]
+    fn easing_ends() {
+        let all = [Ease::Linear, Ease::QuadIn, Ease::QuadOut, Ease::QuadInOut, Ease::CubicIn,
+            Ease::CubicOut, Ease::CubicInOut, Ease::SineIn, Ease::SineOut, Ease::SineInOut,
+            Ease::BackOut, Ease::ElasticOut, Ease::BounceOut];
+        for ease in &all {
+            assert!(ease.apply(0.).abs() < 1e-5, "{:?}", ease);
+            assert!((ease.apply(1.) - 1.).abs() < 1e-5, "{:?}", ease);
+        }
+        assert!(Ease::BackOut.apply(0.8) > 1.);
+    }
+}
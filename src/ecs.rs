@@ -2,12 +2,14 @@
 use specs::prelude::*;
 use crate::math::*;
 use std::time::Instant;
-use specs_hierarchy::Parent;
+use specs_hierarchy::{Hierarchy, HierarchyEvent, Parent};
+use specs::shrev::ReaderId;
+use specs::storage::ComponentEvent;
+use specs::world::EntitiesRes;
 use crate::proto::*;
+use crate::name::NameIndex;
 use serde::{Serialize, Deserialize};
-use specs_derive::*;
-use std::pin::Pin;
-use futures::Future;
+use schemars::{JsonSchema, gen::SchemaGenerator, schema::Schema};
 
 const MAX_DELTA_TIME: f32 = 0.1;
 
@@ -39,21 +41,55 @@ impl Time {
     }
 }
 
-/// A generic 3d transform.
+pub static DEP_HIERARCHY: &str = "hierarchy";
+pub static DEP_TRANSFORM: &str = "transform";
+
+/// A generic 3d transform, relative to the parent's if the entity has `HasParent`.
+/// Renderers use the `GlobalTransform` computed from it.
 #[derive(Serialize, Deserialize, Clone)]
-#[derive(Component)]
-#[storage(VecStorage)]
 pub struct Transform {
     #[serde(default="_vec3_zero")]
     pub pos: Vec3,
     #[serde(default="_quat_identity")]
     pub rot: Quat,
+    #[serde(default="_vec3_one")]
+    pub scale: Vec3,
+}
+
+impl Component for Transform {
+    // Flagged for `TransformSystem`
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
+}
+
+impl JsonSchema for Transform {
+    fn schema_name() -> String {
+        "Transform".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        // glam vectors are serialized as arrays
+        #[derive(JsonSchema)]
+        #[allow(dead_code)]
+        struct TransformSchema {
+            #[serde(default)]
+            pos: [f32; 3],
+            #[serde(default)]
+            rot: [f32; 4],
+            #[serde(default)]
+            scale: [f32; 3]
+        }
+        TransformSchema::json_schema(gen)
+    }
 }
 
 fn _vec3_zero() -> Vec3 {
     Vec3::zero()
 }
 
+fn _vec3_one() -> Vec3 {
+    Vec3::one()
+}
+
 fn _quat_identity() -> Quat {
     Quat::identity()
 }
@@ -63,7 +99,8 @@ impl Transform {
     pub fn new() -> Self {
         Self {
             pos: vec3(0., 0., 0.),
-            rot: Quat::identity()
+            rot: Quat::identity(),
+            scale: Vec3::one()
         }
     }
 
@@ -77,6 +114,16 @@ impl Transform {
         self
     }
 
+    pub fn scale(mut self, s: Vec3) -> Self {
+        self.scale = s;
+        self
+    }
+
+    /// Scales, then rotates, then translates.
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rot, self.pos)
+    }
+
     pub fn get_world_view(&self) -> Mat4 {
         let rot = Mat4::from_quat(self.rot);
         let world_view = Mat4::from_translation(-self.pos) * rot;
@@ -85,6 +132,253 @@ impl Transform {
 
 }
 
+/// Transform for 2d games, in the xy plane. Like `Transform` it's relative to the parent and
+/// turned into a `GlobalTransform`. If an entity has both, `Transform` is used.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Transform2D {
+    #[serde(default="_vec2_zero")]
+    pub pos: Vec2,
+    /// Counterclockwise rotation in radians.
+    #[serde(default)]
+    pub rot: f32,
+    #[serde(default="_vec2_one")]
+    pub scale: Vec2,
+    /// Z coordinate. Sprites with larger depth are drawn over those with smaller.
+    #[serde(default)]
+    pub depth: f32,
+}
+
+impl Component for Transform2D {
+    // Flagged for `TransformSystem`
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
+}
+
+impl JsonSchema for Transform2D {
+    fn schema_name() -> String {
+        "Transform2D".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        #[derive(JsonSchema)]
+        #[allow(dead_code)]
+        struct Transform2DSchema {
+            #[serde(default)]
+            pos: [f32; 2],
+            #[serde(default)]
+            rot: f32,
+            #[serde(default)]
+            scale: [f32; 2],
+            #[serde(default)]
+            depth: f32
+        }
+        Transform2DSchema::json_schema(gen)
+    }
+}
+
+fn _vec2_zero() -> Vec2 {
+    Vec2::zero()
+}
+
+fn _vec2_one() -> Vec2 {
+    Vec2::one()
+}
+
+impl Default for Transform2D {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transform2D {
+
+    pub fn new() -> Self {
+        Self {
+            pos: Vec2::zero(),
+            rot: 0.,
+            scale: Vec2::one(),
+            depth: 0.
+        }
+    }
+
+    pub fn pos(mut self, p: Vec2) -> Self {
+        self.pos = p;
+        self
+    }
+
+    pub fn rot(mut self, r: f32) -> Self {
+        self.rot = r;
+        self
+    }
+
+    pub fn scale(mut self, s: Vec2) -> Self {
+        self.scale = s;
+        self
+    }
+
+    pub fn depth(mut self, d: f32) -> Self {
+        self.depth = d;
+        self
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Transform::from(self).matrix()
+    }
+
+    /// Drops rotation around the x and y axes, and scale along z.
+    pub fn from_transform(t: &Transform) -> Self {
+        // Angle of the rotation around z, exact if the rotation has no other component
+        let rot = 2. * t.rot.z.atan2(t.rot.w);
+        Self {
+            pos: vec2(t.pos.x, t.pos.y),
+            rot,
+            scale: vec2(t.scale.x, t.scale.y),
+            depth: t.pos.z
+        }
+    }
+
+}
+
+impl From<&Transform2D> for Transform {
+    fn from(t: &Transform2D) -> Self {
+        Transform {
+            pos: vec3(t.pos.x, t.pos.y, t.depth),
+            rot: Quat::from_rotation_z(t.rot),
+            scale: vec3(t.scale.x, t.scale.y, 1.)
+        }
+    }
+}
+
+impl From<&Transform> for Transform2D {
+    fn from(t: &Transform) -> Self {
+        Transform2D::from_transform(t)
+    }
+}
+
+/// World space matrix of an entity with `Transform` or `Transform2D`, computed by
+/// `TransformSystem` every frame after `HierarchySystem`.
+#[derive(Clone, Copy, Debug)]
+pub struct GlobalTransform(pub Mat4);
+
+impl Default for GlobalTransform {
+    fn default() -> Self {
+        GlobalTransform(Mat4::identity())
+    }
+}
+
+impl GlobalTransform {
+
+    pub fn position(&self) -> Vec3 {
+        self.0.w_axis.truncate()
+    }
+
+}
+
+impl Component for GlobalTransform {
+    type Storage = VecStorage<Self>;
+}
+
+/// Updates `GlobalTransform`s of changed `Transform`s or `Transform2D`s and their descendants,
+/// parents first. A parent without either passes on the transform it inherited.
+pub struct TransformSystem {
+    transform_reader: ReaderId<ComponentEvent>,
+    transform_2d_reader: ReaderId<ComponentEvent>,
+    hierarchy_reader: ReaderId<HierarchyEvent>,
+    dirty: BitSet,
+    removed: BitSet
+}
+
+impl TransformSystem {
+
+    /// Needs the `Hierarchy<HasParent>` resource, i.e. `HierarchySystem` created first.
+    pub fn new(world: &mut World) -> Self {
+        world.register::<Transform>();
+        world.register::<Transform2D>();
+        world.register::<GlobalTransform>();
+        Self {
+            transform_reader: world.write_storage::<Transform>().register_reader(),
+            transform_2d_reader: world.write_storage::<Transform2D>().register_reader(),
+            hierarchy_reader: world.fetch_mut::<Hierarchy<HasParent>>().track(),
+            dirty: BitSet::new(),
+            removed: BitSet::new()
+        }
+    }
+
+}
+
+impl<'a> System<'a> for TransformSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Transform2D>,
+        ReadStorage<'a, HasParent>,
+        ReadExpect<'a, Hierarchy<HasParent>>,
+        WriteStorage<'a, GlobalTransform>
+    );
+
+    fn run(&mut self, (entities, transforms, transforms_2d, parents, hierarchy, mut globals): Self::SystemData) {
+        self.dirty.clear();
+        self.removed.clear();
+        let events = transforms.channel().read(&mut self.transform_reader)
+            .chain(transforms_2d.channel().read(&mut self.transform_2d_reader));
+        for event in events {
+            match event {
+                // Removing one of both changes the transform used
+                ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) => { self.dirty.add(*id); },
+                ComponentEvent::Removed(id) => {
+                    self.dirty.add(*id);
+                    self.removed.add(*id);
+                }
+            }
+        }
+        // Entities whose parent changed or that became roots
+        for event in hierarchy.changed().read(&mut self.hierarchy_reader) {
+            match event {
+                HierarchyEvent::Modified(e) | HierarchyEvent::Removed(e) => { self.dirty.add(e.id()); }
+            }
+        }
+
+        let local_matrix = |e: Entity| transforms.get(e).map(|x| x.matrix())
+            .or_else(|| transforms_2d.get(e).map(|x| x.matrix()));
+
+        for (e, _, _, _) in (&entities, !&transforms, !&transforms_2d, &self.removed).join() {
+            globals.remove(e);
+        }
+
+        for (e, _, _) in (&entities, !&parents, &self.dirty).join() {
+            if let Some(m) = local_matrix(e) {
+                globals.insert(e, GlobalTransform(m)).unwrap();
+            }
+        }
+
+        // Sorted parents first, so a parent's global transform is up to date before its children
+        for e in hierarchy.all() {
+            let parent = match parents.get(*e) {
+                Some(x) => x.parent,
+                None => continue
+            };
+            if self.dirty.contains(parent.id()) {
+                self.dirty.add(e.id());
+            }
+            if !self.dirty.contains(e.id()) {
+                continue
+            }
+            if let Some(m) = local_matrix(*e) {
+                // The nearest ancestor with a transform, those are already updated
+                let mut ancestor = Some(parent);
+                let parent_matrix = loop {
+                    match ancestor {
+                        Some(a) if transforms.contains(a) || transforms_2d.contains(a) =>
+                            break globals.get(a).map_or(Mat4::identity(), |x| x.0),
+                        Some(a) => ancestor = parents.get(a).map(|x| x.parent),
+                        None => break Mat4::identity()
+                    }
+                };
+                globals.insert(*e, GlobalTransform(parent_matrix * m)).unwrap();
+            }
+        }
+    }
+}
+
 /// Generic parent component used for `specs-hierarchy`.
 /// for detailed usage see [specs-hierarchy site](https://github.com/rustgd/specs-hierarchy)
 #[derive(Debug, Copy, Clone, Eq, Ord, PartialOrd, PartialEq)]
@@ -110,7 +404,7 @@ impl Parent for HasParent {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 struct HasParentS11nData {
     entity_ix: usize
 }
@@ -118,34 +412,354 @@ struct HasParentS11nData {
 #[derive(Clone)]
 pub struct HasParentS11n;
 
-impl ComponentS11n<'_> for HasParentS11n {
+impl<'a> ComponentS11n<'a> for HasParentS11n {
     type SystemData = ();
-    type StoreSystemData = ();
+    type StoreSystemData = Read<'a, NameIndex>;
     type Output = HasParent;
+    type Loaded = HasParent;
 
-    fn load_async(&mut self, ctx: ComponentLoadArgs, _: &mut Self::SystemData) -> Pin<Box<dyn Future<Output=Self::Output> + Send + Sync>> {
-        let s11n: HasParentS11nData = serde_json::from_value(ctx.data).unwrap();
-        let ent = ctx.all_entity_vec[s11n.entity_ix];
-        Box::pin(async move {
-            HasParent {
+    fn load_async(&mut self, ctx: ComponentLoadArgs, _: &mut Self::SystemData) -> Result<ComponentLoadFuture<Self::Loaded>, ProtoErrorKind> {
+        let s11n: HasParentS11nData = serde_json::from_value(ctx.data)?;
+        let ent = *ctx.all_entity_vec.get(s11n.entity_ix)
+            .ok_or_else(|| ProtoErrorKind::Invalid(format!("Parent entity_ix {} out of range", s11n.entity_ix)))?;
+        Ok(Box::pin(async move {
+            Ok(HasParent {
                 parent: ent
-            }
-        })
+            })
+        }))
     }
 
-    fn store(&mut self, ctx: ComponentStoreArgs<Self::Output>, _: &mut Self::StoreSystemData) -> serde_json::Value {
+    fn integrate(&mut self, loaded: Self::Loaded, _: &mut Self::SystemData) -> Result<Self::Output, ProtoErrorKind> {
+        Ok(loaded)
+    }
+
+    fn store(&mut self, ctx: ComponentStoreArgs<Self::Output>, names: &mut Self::StoreSystemData) -> Option<serde_json::Value> {
         let ix = ctx.all_entity_vec.iter()
-            .enumerate()
-            .find_map(|(idx, e)|
-                if *e == ctx.component.parent { Some(idx) }
-                else { None })
-            .expect("Failed to find parent in serializing entities");
+            .position(|e| *e == ctx.component.parent);
+        let ix = match ix {
+            Some(ix) => ix,
+            None => {
+                // Parent is outside of the stored entities, store as root
+                warn!("Parent {} of stored entity {} isn't stored, skipping HasParent",
+                      names.label(ctx.component.parent), names.label(ctx.all_entity_vec[ctx.entity_idx]));
+                return None
+            }
+        };
 
         let s11n = HasParentS11nData {
             entity_ix: ix
         };
-        serde_json::to_value(s11n).unwrap()
+        Some(serde_json::to_value(s11n).unwrap())
     }
 
     fn type_name(&self) -> &'static str { "HasParent" }
+
+    fn schema(&self) -> Option<serde_json::Value> {
+        Some(schema_for::<HasParentS11nData>())
+    }
+}
+
+/// Order of an entity among its siblings, see `HierarchyData::children`. Entities without one
+/// count as 0, ties keep the order they were parented in.
+#[derive(Serialize, Deserialize, JsonSchema, Copy, Clone, Debug, Default, Eq, Ord, PartialOrd, PartialEq)]
+#[serde(transparent)]
+pub struct SiblingOrder(pub i32);
+
+impl Component for SiblingOrder {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Deletes `entity` and all its `HasParent` descendants.
+///
+/// Descendants are read from the `Hierarchy`, which doesn't include parents set this frame
+/// before `HierarchySystem` runs.
+pub fn despawn_recursive(entities: &EntitiesRes, hierarchy: &Hierarchy<HasParent>, entity: Entity) {
+    let descendants: Vec<Entity> = hierarchy.all_children_iter(entity).collect();
+    for e in std::iter::once(entity).chain(descendants) {
+        // Already deleted entities are fine, e.g. from an earlier call this frame
+        let _ = entities.delete(e);
+    }
+}
+
+/// `SystemData` for querying and changing the `HasParent` hierarchy.
+///
+/// Parents are read from the `HasParent` storage, so `set_parent`, `ancestors` and `root_of`
+/// see changes made this frame. Children are read from the `Hierarchy`, which is only updated
+/// when `HierarchySystem` runs.
+#[derive(SystemData)]
+pub struct HierarchyData<'a> {
+    entities: Entities<'a>,
+    hierarchy: ReadExpect<'a, Hierarchy<HasParent>>,
+    parents: WriteStorage<'a, HasParent>,
+    orders: WriteStorage<'a, SiblingOrder>,
+    transforms: WriteStorage<'a, Transform>,
+    transforms_2d: WriteStorage<'a, Transform2D>
+}
+
+impl<'a> HierarchyData<'a> {
+
+    pub fn parent(&self, entity: Entity) -> Option<Entity> {
+        self.parents.get(entity).map(|x| x.parent)
+    }
+
+    /// Direct children of the entity, sorted by `SiblingOrder`.
+    pub fn children(&self, entity: Entity) -> Vec<Entity> {
+        let mut ret = self.hierarchy.children(entity).to_vec();
+        // Stable, so ties keep the hierarchy's order
+        ret.sort_by_key(|e| self.orders.get(*e).copied().unwrap_or_default());
+        ret
+    }
+
+    /// All descendants of the entity, depth first with children sorted by `SiblingOrder`.
+    pub fn descendants(&self, entity: Entity) -> Vec<Entity> {
+        let mut ret = vec![];
+        let mut stack: Vec<Entity> = self.children(entity).into_iter().rev().collect();
+        while let Some(e) = stack.pop() {
+            ret.push(e);
+            stack.extend(self.children(e).into_iter().rev());
+        }
+        ret
+    }
+
+    /// Parent, grandparent and so on up to the root.
+    pub fn ancestors(&self, entity: Entity) -> Vec<Entity> {
+        let mut ret: Vec<Entity> = vec![];
+        let mut cur = entity;
+        while let Some(parent) = self.parent(cur) {
+            // Stop at parent cycles instead of looping forever
+            if parent == entity || ret.contains(&parent) {
+                break
+            }
+            ret.push(parent);
+            cur = parent;
+        }
+        ret
+    }
+
+    /// The topmost ancestor, or the entity itself if it has no parent.
+    pub fn root_of(&self, entity: Entity) -> Entity {
+        self.ancestors(entity).last().copied().unwrap_or(entity)
+    }
+
+    /// Changes the parent of `child`, or makes it a root with `None`. With
+    /// `keep_world_transform` its `Transform` or `Transform2D` is adjusted so it stays in place.
+    ///
+    /// Returns false and changes nothing if `parent` is `child` or one of its descendants.
+    pub fn set_parent(&mut self, child: Entity, parent: Option<Entity>, keep_world_transform: bool) -> bool {
+        if let Some(parent) = parent {
+            if parent == child || self.ancestors(parent).contains(&child) {
+                return false
+            }
+        }
+
+        if keep_world_transform {
+            if let Some(world) = self.world_matrix(child) {
+                let parent_world = parent.and_then(|p| self.world_matrix(p)).unwrap_or(Mat4::identity());
+                let (scale, rot, pos) = (parent_world.inverse() * world).to_scale_rotation_translation();
+                let local = Transform::new().pos(pos).rot(rot).scale(scale);
+                if self.transforms.contains(child) {
+                    self.transforms.insert(child, local).unwrap();
+                } else {
+                    self.transforms_2d.insert(child, Transform2D::from(&local)).unwrap();
+                }
+            }
+        }
+
+        match parent {
+            Some(parent) => { self.parents.insert(child, HasParent::new(parent)).unwrap(); },
+            None => { self.parents.remove(child); }
+        }
+        true
+    }
+
+    /// Moves the entity to `index` among its siblings, renumbering their `SiblingOrder`s. An
+    /// index past the end moves it last. Does nothing for roots.
+    pub fn set_sibling_index(&mut self, entity: Entity, index: usize) {
+        let parent = match self.parent(entity) {
+            Some(x) => x,
+            None => return
+        };
+        let mut siblings: Vec<Entity> = self.children(parent).into_iter()
+            .filter(|e| *e != entity)
+            .collect();
+        siblings.insert(index.min(siblings.len()), entity);
+        for (i, e) in siblings.into_iter().enumerate() {
+            self.orders.insert(e, SiblingOrder(i as i32)).unwrap();
+        }
+    }
+
+    /// See `despawn_recursive`.
+    pub fn despawn_recursive(&self, entity: Entity) {
+        despawn_recursive(&self.entities, &self.hierarchy, entity);
+    }
+
+    /// World matrix computed from the local transforms of the entity and its ancestors, as
+    /// `TransformSystem` would. `None` if the entity has no transform.
+    fn world_matrix(&self, entity: Entity) -> Option<Mat4> {
+        let local_matrix = |e: Entity| self.transforms.get(e).map(|x| x.matrix())
+            .or_else(|| self.transforms_2d.get(e).map(|x| x.matrix()));
+
+        let local = local_matrix(entity)?;
+        let mut parent_matrix = Mat4::identity();
+        for e in self.ancestors(entity).into_iter().rev() {
+            // A parent without a transform passes on what it inherited
+            if let Some(m) = local_matrix(e) {
+                parent_matrix = parent_matrix * m;
+            }
+        }
+        Some(parent_matrix * local)
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use specs_hierarchy::HierarchySystem;
+
+    use super::*;
+
+    #[test]
+    fn global_transform_follows_parent() {
+        let mut world = World::new();
+        let mut hierarchy_system = HierarchySystem::<HasParent>::new(&mut world);
+        let mut transform_system = TransformSystem::new(&mut world);
+        let mut run = |world: &mut World| {
+            hierarchy_system.run_now(world);
+            transform_system.run_now(world);
+            world.maintain();
+        };
+
+        let root = world.create_entity()
+            .with(Transform::new().pos(vec3(1., 0., 0.)).scale(vec3(2., 2., 2.)))
+            .build();
+        let child = world.create_entity()
+            .with(Transform::new().pos(vec3(0., 1., 0.)))
+            .with(HasParent::new(root))
+            .build();
+        // Created before its parent
+        let grandchild = world.create_entity()
+            .with(Transform::new().pos(vec3(0., 0., 1.)))
+            .build();
+        world.write_storage::<HasParent>().insert(grandchild, HasParent::new(child)).unwrap();
+        run(&mut world);
+
+        let global = |world: &World, e| world.read_storage::<GlobalTransform>().get(e).unwrap().position();
+        assert_eq!(global(&world, root), vec3(1., 0., 0.));
+        assert_eq!(global(&world, child), vec3(1., 2., 0.));
+        assert_eq!(global(&world, grandchild), vec3(1., 2., 2.));
+
+        // Moving the root moves the descendants
+        world.write_storage::<Transform>().get_mut(root).unwrap().pos = vec3(5., 0., 0.);
+        run(&mut world);
+        assert_eq!(global(&world, grandchild), vec3(5., 2., 2.));
+
+        // Moving the child to another parent
+        let other = world.create_entity().with(Transform::new()).build();
+        world.write_storage::<HasParent>().insert(child, HasParent::new(other)).unwrap();
+        run(&mut world);
+        assert_eq!(global(&world, child), vec3(0., 1., 0.));
+        assert_eq!(global(&world, grandchild), vec3(0., 1., 1.));
+
+        world.write_storage::<Transform>().remove(root);
+        run(&mut world);
+        assert!(world.read_storage::<GlobalTransform>().get(root).is_none());
+    }
+
+    #[test]
+    fn transform_2d() {
+        let t2d = Transform2D::new().pos(vec2(1., 2.)).rot(0.5).scale(vec2(2., 3.)).depth(4.);
+        let t = Transform::from(&t2d);
+        assert_eq!(t.pos, vec3(1., 2., 4.));
+        assert_eq!(t.scale, vec3(2., 3., 1.));
+        let back = Transform2D::from(&t);
+        assert!((back.rot - 0.5).abs() < 1e-5);
+        assert_eq!((back.pos, back.scale, back.depth), (t2d.pos, t2d.scale, t2d.depth));
+
+        // Mixed with 3d transforms in the hierarchy
+        let mut world = World::new();
+        let mut hierarchy_system = HierarchySystem::<HasParent>::new(&mut world);
+        let mut transform_system = TransformSystem::new(&mut world);
+        let root = world.create_entity()
+            .with(Transform2D::new().pos(vec2(1., 0.)).rot(std::f32::consts::FRAC_PI_2))
+            .build();
+        let child = world.create_entity()
+            .with(Transform::new().pos(vec3(1., 0., 0.)))
+            .with(HasParent::new(root))
+            .build();
+        hierarchy_system.run_now(&world);
+        transform_system.run_now(&world);
+
+        let pos = world.read_storage::<GlobalTransform>().get(child).unwrap().position();
+        assert!(vec2_approx_eq(vec2(pos.x, pos.y), vec2(1., 1.)), "{:?}", pos);
+    }
+
+    #[test]
+    fn hierarchy_data() {
+        let mut world = World::new();
+        world.register::<SiblingOrder>();
+        let mut hierarchy_system = HierarchySystem::<HasParent>::new(&mut world);
+        let mut transform_system = TransformSystem::new(&mut world);
+        let mut run = |world: &mut World| {
+            hierarchy_system.run_now(world);
+            transform_system.run_now(world);
+            world.maintain();
+        };
+
+        let root = world.create_entity()
+            .with(Transform::new().pos(vec3(1., 0., 0.)).scale(vec3(2., 2., 2.)))
+            .build();
+        let a = world.create_entity().with(HasParent::new(root)).build();
+        let b = world.create_entity().with(HasParent::new(root)).build();
+        let c = world.create_entity()
+            .with(Transform::new().pos(vec3(0., 1., 0.)))
+            .with(HasParent::new(a))
+            .build();
+        let other = world.create_entity()
+            .with(Transform2D::new().pos(vec2(0., 5.)))
+            .build();
+        run(&mut world);
+        // `a` has no transform and passes on the one of `root`
+        assert_eq!(world.read_storage::<GlobalTransform>().get(c).unwrap().position(), vec3(1., 2., 0.));
+
+        {
+            let mut data = world.system_data::<HierarchyData>();
+            assert_eq!(data.children(root), vec![a, b]);
+            assert_eq!(data.descendants(root), vec![a, c, b]);
+            assert_eq!(data.ancestors(c), vec![a, root]);
+            assert_eq!(data.world_matrix(c).unwrap().w_axis.truncate(), vec3(1., 2., 0.));
+            assert_eq!(data.root_of(c), root);
+            assert_eq!(data.root_of(root), root);
+
+            data.set_sibling_index(b, 0);
+            assert_eq!(data.children(root), vec![b, a]);
+            assert_eq!(data.descendants(root), vec![b, a, c]);
+
+            // No cycles
+            assert!(!data.set_parent(root, Some(c), false));
+            assert!(!data.set_parent(a, Some(a), false));
+            assert!(data.set_parent(c, Some(other), true));
+            assert_eq!(data.parent(c), Some(other));
+        }
+        run(&mut world);
+
+        // Stays in place under the 2d parent. `a` has no transform, so `c` inherited the one of `root`
+        let global = |world: &World, e| world.read_storage::<GlobalTransform>().get(e).unwrap().position();
+        let near = |v: Vec3, expected: Vec3| (v - expected).length() < 1e-5;
+        assert_eq!(world.read_storage::<Transform>().get(c).unwrap().pos, vec3(1., -3., 0.));
+        assert!(near(global(&world, c), vec3(1., 2., 0.)), "{:?}", global(&world, c));
+
+        {
+            let mut data = world.system_data::<HierarchyData>();
+            assert!(data.set_parent(c, None, true));
+        }
+        run(&mut world);
+        assert!(near(global(&world, c), vec3(1., 2., 0.)));
+        assert!(world.read_storage::<HasParent>().get(c).is_none());
+
+        world.system_data::<HierarchyData>().despawn_recursive(root);
+        world.maintain();
+        assert!(!world.is_alive(a));
+        assert!(!world.is_alive(b));
+        assert!(world.is_alive(c));
+    }
 }
@@ -20,12 +20,16 @@ pub type WindowEventLoop = event_loop::EventLoop<()>;
 
 #[macro_use]
 pub extern crate log;
+// Allows `::mu::` paths generated by mu-derive inside this crate
+extern crate self as mu;
 use winit::dpi::PhysicalSize;
 use std::collections::HashSet;
 
 pub use specs;
 pub use bytemuck;
 pub use glam;
+pub use serde_json;
+pub use schemars;
 
 pub use wgpu;
 
@@ -35,6 +39,10 @@ pub mod ecs;
 pub mod math;
 pub mod util;
 pub mod proto;
+pub mod scene;
+pub mod prefab;
+pub mod savegame;
+pub mod name;
 pub mod client;
 
 /// Helper struct for adding a sorted system.
@@ -408,7 +416,11 @@ impl RuntimeBuilder {
 
     fn _default_modules() -> Vec<Box<dyn Module>> {
         vec![
-            Box::new(proto::ProtoModule)
+            Box::new(proto::ProtoModule),
+            Box::new(scene::SceneModule),
+            Box::new(prefab::PrefabModule),
+            Box::new(savegame::SaveGameModule),
+            Box::new(name::NameModule)
         ]
     }
 
@@ -475,7 +487,7 @@ impl RuntimeBuilder {
             res_mgr, client_data.window.clone(), world, existing_modules);
 
         // Default systems
-        dispatcher_builder.add(HierarchySystem::<HasParent>::new(&mut init_ctx.init_data.world), "", &[]);
+        dispatcher_builder.add(HierarchySystem::<HasParent>::new(&mut init_ctx.init_data.world), ecs::DEP_HIERARCHY, &[]);
 
         // Module init
         for game_module in &mut self.modules {
@@ -483,13 +495,13 @@ impl RuntimeBuilder {
         }
 
         // Default serialized components
-        {
-            use proto::InitContextProtoExt;
-            init_ctx.add_component_s11n(proto::ComponentS11nDefault::<ecs::Transform>::new("Transform"));
-            init_ctx.add_component_s11n(ecs::HasParentS11n);
-        }
+        proto::register_default_components(&mut init_ctx);
 
         let mut world = init_ctx.post_dispatch(&mut dispatcher_builder);
+        // Behind a barrier, so it runs after the module systems moving entities. Without it, shred
+        // may put it in an earlier stage than a system writing `Transform`
+        dispatcher_builder.add_barrier();
+        dispatcher_builder.add(ecs::TransformSystem::new(&mut world), ecs::DEP_TRANSFORM, &[ecs::DEP_HIERARCHY]);
 
         let mut dispatcher = dispatcher_builder.build();
         dispatcher.setup(&mut world);
@@ -0,0 +1,303 @@
+//! Entity names and tags, and looking up entities by them.
+//!
+//! `NameIndex` is updated by `NameSystem` from the storage events of `Name` and `Tags`, so
+//! changes made this frame can be found once it has run.
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use specs::prelude::*;
+use specs::shrev::ReaderId;
+use specs::storage::ComponentEvent;
+use specs::world::Index;
+use specs_hierarchy::Hierarchy;
+
+use crate::{InitContext, InsertInfo, Module};
+use crate::ecs::HasParent;
+
+pub static DEP_NAME: &str = "name";
+
+/// Name of an entity, used in lookups, editor panels and log messages. Names don't need to be
+/// unique, but path lookups only make sense if siblings have different names.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Name(pub String);
+
+impl Name {
+
+    pub fn new(name: &str) -> Self {
+        Name(name.to_string())
+    }
+
+}
+
+impl Component for Name {
+    // Flagged for `NameSystem`
+    type Storage = FlaggedStorage<Self, DenseVecStorage<Self>>;
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Tags(pub Vec<String>);
+
+impl Tags {
+
+    pub fn new(tags: &[&str]) -> Self {
+        Tags(tags.iter().map(|x| x.to_string()).collect())
+    }
+
+    pub fn has(&self, tag: &str) -> bool {
+        self.0.iter().any(|x| x == tag)
+    }
+
+}
+
+impl Component for Tags {
+    // Flagged for `NameSystem`
+    type Storage = FlaggedStorage<Self, DenseVecStorage<Self>>;
+}
+
+/// Formats the entity for log messages, e.g. `Door (3v1)`, or `3v1` without a name.
+pub fn entity_label(entity: Entity, name: Option<&Name>) -> String {
+    match name {
+        Some(name) => format!("{} ({}v{})", name.0, entity.id(), entity.gen().id()),
+        None => format!("{}v{}", entity.id(), entity.gen().id())
+    }
+}
+
+/// A `Resource` for finding entities by `Name` or `Tags`.
+#[derive(Default)]
+pub struct NameIndex {
+    by_name: HashMap<String, Vec<Entity>>,
+    by_tag: HashMap<String, Vec<Entity>>,
+    // Indexed contents by entity, to unindex them when changed or removed
+    names: HashMap<Index, (Entity, Name)>,
+    tags: HashMap<Index, (Entity, Tags)>
+}
+
+impl NameIndex {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Any entity with the name, the first one named so if there are several.
+    pub fn find(&self, name: &str) -> Option<Entity> {
+        self.find_all(name).first().copied()
+    }
+
+    /// All entities with the name, in the order they were named.
+    pub fn find_all(&self, name: &str) -> &[Entity] {
+        self.by_name.get(name).map(|x| x.as_slice()).unwrap_or(&[])
+    }
+
+    /// All entities with the tag, in the order they were tagged.
+    pub fn with_tag(&self, tag: &str) -> &[Entity] {
+        self.by_tag.get(tag).map(|x| x.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn name_of(&self, entity: Entity) -> Option<&Name> {
+        self.names.get(&entity.id())
+            .filter(|(e, _)| *e == entity)
+            .map(|(_, name)| name)
+    }
+
+    pub fn tags_of(&self, entity: Entity) -> Option<&Tags> {
+        self.tags.get(&entity.id())
+            .filter(|(e, _)| *e == entity)
+            .map(|(_, tags)| tags)
+    }
+
+    /// See `entity_label`.
+    pub fn label(&self, entity: Entity) -> String {
+        entity_label(entity, self.name_of(entity))
+    }
+
+    /// The child of `parent` with the name.
+    pub fn find_child(&self, hierarchy: &Hierarchy<HasParent>, parent: Entity, name: &str) -> Option<Entity> {
+        hierarchy.children(parent).iter()
+            .copied()
+            .find(|e| self.name_of(*e).map(|x| x.0 == name).unwrap_or(false))
+    }
+
+    /// Finds an entity by the names along its `HasParent` chain, e.g. `"Level/Door/Switch"` is
+    /// the `Switch` child of the `Door` child of a root named `Level`.
+    pub fn find_path(&self, hierarchy: &Hierarchy<HasParent>, path: &str) -> Option<Entity> {
+        let mut segments = path.split('/').filter(|x| !x.is_empty());
+        let first = segments.next()?;
+        let mut candidates: Vec<Entity> = self.find_all(first).iter()
+            .copied()
+            .filter(|e| hierarchy.parent(*e).is_none())
+            .collect();
+
+        // Names may repeat, so every match of a segment is tried
+        for segment in segments {
+            candidates = candidates.iter()
+                .flat_map(|parent| hierarchy.children(*parent).iter().copied())
+                .filter(|e| self.name_of(*e).map(|x| x.0 == segment).unwrap_or(false))
+                .collect();
+        }
+        candidates.first().copied()
+    }
+
+    fn unindex_name(&mut self, id: Index) {
+        if let Some((entity, name)) = self.names.remove(&id) {
+            remove_entry(&mut self.by_name, &name.0, entity);
+        }
+    }
+
+    fn unindex_tags(&mut self, id: Index) {
+        if let Some((entity, tags)) = self.tags.remove(&id) {
+            for tag in &tags.0 {
+                remove_entry(&mut self.by_tag, tag, entity);
+            }
+        }
+    }
+
+}
+
+fn remove_entry(map: &mut HashMap<String, Vec<Entity>>, key: &str, entity: Entity) {
+    if let Some(list) = map.get_mut(key) {
+        list.retain(|x| *x != entity);
+        if list.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
+/// Keeps `NameIndex` in sync with the `Name` and `Tags` storages.
+pub struct NameSystem {
+    name_reader: ReaderId<ComponentEvent>,
+    tags_reader: ReaderId<ComponentEvent>
+}
+
+impl NameSystem {
+
+    pub fn new(world: &mut World) -> Self {
+        world.register::<Name>();
+        world.register::<Tags>();
+        Self {
+            name_reader: world.write_storage::<Name>().register_reader(),
+            tags_reader: world.write_storage::<Tags>().register_reader()
+        }
+    }
+
+}
+
+impl<'a> System<'a> for NameSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Tags>,
+        Write<'a, NameIndex>
+    );
+
+    fn run(&mut self, (entities, names, tags, mut index): Self::SystemData) {
+        for event in names.channel().read(&mut self.name_reader) {
+            let id = match event {
+                ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) | ComponentEvent::Removed(id) => *id
+            };
+            index.unindex_name(id);
+
+            let entity = entities.entity(id);
+            if let Some(name) = names.get(entity) {
+                index.by_name.entry(name.0.clone()).or_default().push(entity);
+                index.names.insert(id, (entity, name.clone()));
+            }
+        }
+
+        for event in tags.channel().read(&mut self.tags_reader) {
+            let id = match event {
+                ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) | ComponentEvent::Removed(id) => *id
+            };
+            index.unindex_tags(id);
+
+            let entity = entities.entity(id);
+            if let Some(entity_tags) = tags.get(entity) {
+                for tag in &entity_tags.0 {
+                    let list = index.by_tag.entry(tag.clone()).or_default();
+                    // Tags listed twice are indexed once
+                    if !list.contains(&entity) {
+                        list.push(entity);
+                    }
+                }
+                index.tags.insert(id, (entity, entity_tags.clone()));
+            }
+        }
+    }
+}
+
+pub(super) struct NameModule;
+
+impl Module for NameModule {
+    fn init(&self, ctx: &mut InitContext) {
+        ctx.init_data.world.insert(NameIndex::new());
+        ctx.dispatch(InsertInfo::new(DEP_NAME),
+                     |init_data, i| i.insert(NameSystem::new(&mut init_data.world)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use specs_hierarchy::HierarchySystem;
+
+    use super::*;
+
+    #[test]
+    fn lookup_by_name_tag_and_path() {
+        let mut world = World::new();
+        let mut hierarchy_system = HierarchySystem::<HasParent>::new(&mut world);
+        let mut name_system = NameSystem::new(&mut world);
+        world.insert(NameIndex::new());
+        let mut run = |world: &mut World| {
+            hierarchy_system.run_now(world);
+            name_system.run_now(world);
+            world.maintain();
+        };
+
+        let level = world.create_entity().with(Name::new("Level")).build();
+        let door = world.create_entity()
+            .with(Name::new("Door"))
+            .with(Tags::new(&["interactive", "red"]))
+            .with(HasParent::new(level))
+            .build();
+        let switch = world.create_entity()
+            .with(Name::new("Switch"))
+            .with(Tags::new(&["interactive"]))
+            .with(HasParent::new(door))
+            .build();
+        // Same name, but not a root
+        let other_level = world.create_entity()
+            .with(Name::new("Level"))
+            .with(HasParent::new(door))
+            .build();
+        run(&mut world);
+
+        {
+            let index = world.read_resource::<NameIndex>();
+            let hierarchy = world.read_resource::<Hierarchy<HasParent>>();
+            assert_eq!(index.find("Door"), Some(door));
+            assert_eq!(index.find_all("Level"), &[level, other_level]);
+            assert_eq!(index.with_tag("interactive"), &[door, switch]);
+            assert_eq!(index.find_path(&hierarchy, "Level/Door/Switch"), Some(switch));
+            assert_eq!(index.find_path(&hierarchy, "Level/Door/Level"), Some(other_level));
+            assert_eq!(index.find_path(&hierarchy, "Door/Switch"), None);
+            assert_eq!(index.find_child(&hierarchy, level, "Door"), Some(door));
+            assert_eq!(index.label(door), format!("Door ({}v1)", door.id()));
+        }
+
+        // Renaming, retagging and deleting update the index
+        world.write_storage::<Name>().get_mut(door).unwrap().0 = "Gate".to_string();
+        world.write_storage::<Tags>().insert(switch, Tags::new(&["red"])).unwrap();
+        world.delete_entity(other_level).unwrap();
+        run(&mut world);
+
+        let index = world.read_resource::<NameIndex>();
+        assert_eq!(index.find("Door"), None);
+        assert_eq!(index.find("Gate"), Some(door));
+        assert_eq!(index.find_all("Level"), &[level]);
+        assert_eq!(index.with_tag("interactive"), &[door]);
+        assert_eq!(index.with_tag("red"), &[door, switch]);
+        assert!(index.name_of(other_level).is_none());
+    }
+}
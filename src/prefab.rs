@@ -0,0 +1,261 @@
+//! Instances of protos spawned by `ProtoLoadRequest`, and hierarchy-aware deletion.
+//!
+//! When a proto requested with `ProtoLoadRequest::as_instance` finishes loading, its root (the
+//! first entity of the file) gets a `PrefabInstance` recording the source proto and all spawned
+//! entities.
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+
+use specs::prelude::*;
+use specs::world::EntitiesRes;
+use specs_derive::Component;
+use specs_hierarchy::Hierarchy;
+
+use crate::{InitContext, InsertInfo, Module};
+use crate::ecs::{HasParent, Transform, Transform2D};
+pub use crate::ecs::despawn_recursive;
+use crate::name::NameIndex;
+use crate::proto::*;
+use crate::resource::ResManager;
+
+pub static DEP_PREFAB: &str = "prefab";
+
+/// Marks the root of a spawned proto.
+#[derive(Component, Clone, Debug)]
+#[storage(HashMapStorage)]
+pub struct PrefabInstance {
+    /// Path of the proto the instance is spawned from.
+    pub source: String,
+    /// All entities spawned by the proto, the root first.
+    pub entities: Vec<Entity>
+}
+
+/// Deletes all entities of the instance, and their descendants that were attached later.
+pub fn despawn_instance(entities: &EntitiesRes, hierarchy: &Hierarchy<HasParent>, instance: &PrefabInstance) {
+    for e in &instance.entities {
+        if entities.is_alive(*e) {
+            despawn_recursive(entities, hierarchy, *e);
+        }
+    }
+}
+
+/// Respawns a prefab instance. The fields can be changed from the defaults of `reload` and
+/// `revert`, e.g. `PrefabReloadRequest { read_file: false, ..PrefabReloadRequest::revert(root) }`.
+pub struct PrefabReloadRequest {
+    pub root: Entity,
+    /// Whether the new root keeps the `Transform` or `Transform2D` of the old one. The parent is always kept.
+    pub keep_transform: bool,
+    /// Whether the source file is read again, also if its modification time didn't change.
+    /// Otherwise the cached `ProtoTemplate` is spawned, which is only read again once modified.
+    pub read_file: bool,
+    pub result: ProtoLoadResult
+}
+
+impl PrefabReloadRequest {
+
+    /// Respawns the instance from its source proto, reading the file again. The instance keeps
+    /// its place in the world.
+    pub fn reload(root: Entity) -> Self {
+        Self::new(root, true)
+    }
+
+    /// Respawns the instance from its source proto, reading the file again and discarding all
+    /// changes made to the instance.
+    pub fn revert(root: Entity) -> Self {
+        Self::new(root, false)
+    }
+
+    fn new(root: Entity, keep_transform: bool) -> Self {
+        Self {
+            root,
+            keep_transform,
+            read_file: true,
+            result: Arc::new(Mutex::new(Poll::Pending))
+        }
+    }
+
+}
+
+pub enum PrefabRequest {
+    /// Deletes the instance with `root`, see `despawn_instance`.
+    Despawn(Entity),
+    /// The old instance is deleted once the new one is loaded. If loading fails it's kept.
+    Reload(PrefabReloadRequest)
+}
+
+pub type PrefabRequests = Vec<PrefabRequest>;
+
+#[derive(Default)]
+struct PrefabSystem {
+    reloading: Vec<(PrefabReloadRequest, ProtoLoadResult)>
+}
+
+impl<'a> System<'a> for PrefabSystem {
+    type SystemData = (
+        Write<'a, PrefabRequests>,
+        WriteExpect<'a, ProtoLoadRequests>,
+        ReadStorage<'a, PrefabInstance>,
+        WriteStorage<'a, HasParent>,
+        WriteStorage<'a, Transform>,
+        WriteStorage<'a, Transform2D>,
+        ReadExpect<'a, Hierarchy<HasParent>>,
+        WriteExpect<'a, ResManager>,
+        Read<'a, NameIndex>,
+        Entities<'a>
+    );
+
+    fn run(&mut self, (mut requests, mut load_requests, instances, mut parents, mut transforms,
+        mut transforms_2d, hierarchy, mut res_mgr, names, entities): Self::SystemData) {
+        for req in requests.drain(..) {
+            match req {
+                PrefabRequest::Despawn(root) => match instances.get(root) {
+                    Some(instance) => despawn_instance(&entities, &hierarchy, instance),
+                    None => warn!("Despawn: {} isn't a prefab instance root", names.label(root))
+                },
+                PrefabRequest::Reload(req) => match instances.get(req.root) {
+                    Some(instance) => {
+                        if req.read_file {
+                            if let Err(e) = ProtoTemplate::reload(&mut res_mgr, &instance.source) {
+                                *req.result.lock().unwrap() = Poll::Ready(Err(ProtoError::new(&instance.source, e)));
+                                continue
+                            }
+                        }
+                        let load = ProtoLoadRequest::new(&instance.source).as_instance();
+                        self.reloading.push((req, load.result.clone()));
+                        load_requests.push(load);
+                    },
+                    None => {
+                        let e = ProtoError::new(&names.label(req.root),
+                                                ProtoErrorKind::Invalid("Not a prefab instance root".to_string()));
+                        *req.result.lock().unwrap() = Poll::Ready(Err(e));
+                    }
+                }
+            }
+        }
+
+        self.reloading.retain(|(req, load_result)| {
+            let result = match std::mem::replace(&mut *load_result.lock().unwrap(), Poll::Pending) {
+                Poll::Pending => return true,
+                Poll::Ready(result) => result
+            };
+
+            if let Ok(new_entities) = &result {
+                if let (Some(new_root), Some(instance)) = (new_entities.first(), instances.get(req.root)) {
+                    if let Some(parent) = parents.get(req.root).cloned() {
+                        parents.insert(*new_root, parent).unwrap();
+                    }
+                    if req.keep_transform {
+                        if let Some(transform) = transforms.get(req.root).cloned() {
+                            transforms.insert(*new_root, transform).unwrap();
+                        }
+                        if let Some(transform) = transforms_2d.get(req.root).cloned() {
+                            transforms_2d.insert(*new_root, transform).unwrap();
+                        }
+                    }
+                    despawn_instance(&entities, &hierarchy, instance);
+                }
+            }
+            *req.result.lock().unwrap() = Poll::Ready(result);
+            false
+        });
+    }
+}
+
+pub(super) struct PrefabModule;
+
+impl Module for PrefabModule {
+    fn init(&self, ctx: &mut InitContext) {
+        ctx.init_data.world.insert(PrefabRequests::new());
+        // `InitContext::dispatch` doesn't allow before deps
+        ctx.group_normal.dispatch(InsertInfo::new(DEP_PREFAB).before(&[DEP_PROTO_LOAD]),
+                                  |_, i| i.insert(PrefabSystem::default()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use specs_hierarchy::HierarchySystem;
+
+    use crate::math::{Vec2, Vec3};
+    use crate::name::Name;
+    use crate::proto::test_app::TestApp;
+    use super::*;
+
+    #[test]
+    fn despawn_with_descendants() {
+        let mut world = World::new();
+        world.register::<HasParent>();
+        let mut hierarchy_system = HierarchySystem::<HasParent>::new(&mut world);
+
+        let root = world.create_entity().build();
+        let child = world.create_entity().with(HasParent::new(root)).build();
+        let grandchild = world.create_entity().with(HasParent::new(child)).build();
+        let other = world.create_entity().build();
+        let attached = world.create_entity().with(HasParent::new(other)).build();
+        hierarchy_system.run_now(&world);
+
+        despawn_recursive(&world.entities(), &world.fetch(), child);
+        world.maintain();
+        assert!(world.is_alive(root));
+        assert!(!world.is_alive(child));
+        assert!(!world.is_alive(grandchild));
+
+        // Entities attached to the instance later are deleted too
+        let instance = PrefabInstance {
+            source: "a.json".to_string(),
+            entities: vec![root, child, other]
+        };
+        despawn_instance(&world.entities(), &world.fetch(), &instance);
+        world.maintain();
+        assert!(!world.is_alive(root));
+        assert!(!world.is_alive(other));
+        assert!(!world.is_alive(attached));
+    }
+
+    #[test]
+    fn reload_instance() {
+        let path = std::env::temp_dir().join(format!("mu_prefab_test_{}.json", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        std::fs::write(&path, r#"[{ "Name": "Crate", "Transform": {} }, { "Name": "Lid", "HasParent": { "entity_ix": 0 } }]"#).unwrap();
+
+        let mut app = TestApp::new(&[DEP_PREFAB], |_, builder| {
+            builder.add(PrefabSystem::default(), DEP_PREFAB, &[]);
+        });
+        let mut load = |app: &mut TestApp, req: ProtoLoadRequest| {
+            let result = req.result.clone();
+            app.world.write_resource::<ProtoLoadRequests>().push(req);
+            app.run_until(|_| result.lock().unwrap().is_ready());
+            let ret = match &*result.lock().unwrap() {
+                Poll::Ready(Ok(spawned)) => spawned.clone(),
+                _ => panic!("Proto didn't load")
+            };
+            ret
+        };
+
+        let plain = load(&mut app, ProtoLoadRequest::new(&path));
+        assert!(app.world.read_storage::<PrefabInstance>().get(plain[0]).is_none());
+        let root = load(&mut app, ProtoLoadRequest::new(&path).as_instance())[0];
+        assert!(app.world.read_storage::<PrefabInstance>().get(root).is_some());
+
+        app.world.write_storage::<Transform>().get_mut(root).unwrap().pos = Vec3::new(5., 0., 0.);
+        let transform_2d = Transform2D { pos: Vec2::new(1., 2.), ..Transform2D::default() };
+        app.world.write_storage::<Transform2D>().insert(root, transform_2d.clone()).unwrap();
+        std::fs::write(&path, r#"[{ "Name": "Box", "Transform": {}, "Transform2D": {} }]"#).unwrap();
+        let req = PrefabReloadRequest::reload(root);
+        let result = req.result.clone();
+        app.world.write_resource::<PrefabRequests>().push(PrefabRequest::Reload(req));
+        app.run_until(|_| result.lock().unwrap().is_ready());
+        app.run();
+
+        let new_root = match &*result.lock().unwrap() {
+            Poll::Ready(Ok(spawned)) => spawned[0],
+            _ => panic!("Reload failed")
+        };
+        assert!(!app.world.is_alive(root));
+        assert_eq!(app.world.read_storage::<Name>().get(new_root), Some(&Name::new("Box")));
+        assert_eq!(app.world.read_storage::<Transform>().get(new_root).unwrap().pos, Vec3::new(5., 0., 0.));
+        assert_eq!(app.world.read_storage::<Transform2D>().get(new_root), Some(&transform_2d));
+        assert!(app.world.read_storage::<PrefabInstance>().get(new_root).is_some());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,37 +1,181 @@
-use std::collections::HashMap;
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::future::Future;
+use std::io;
 use std::marker::PhantomData;
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::task::Poll;
+use std::time::SystemTime;
 
-use futures::executor::{block_on, ThreadPool};
-use serde::{de::DeserializeOwned, Serialize};
+use futures::executor::ThreadPool;
+use jsonschema::JSONSchema;
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
 use specs::prelude::*;
+use specs::shrev::EventChannel;
+use specs_derive::Component;
 
 use internal::*;
 
 use crate::{InitContext, InsertInfo, Module};
 use crate::asset;
+use crate::asset::LoadableAsset;
+use crate::name::entity_label;
+use crate::prefab::PrefabInstance;
+use crate::resource::{ResManager, ResourceRef, RetentionPolicy};
 
 pub static DEP_PROTO_LOAD: &str = "proto_load";
 pub static DEP_PROTO_STORE: &str = "proto_store";
 
 pub struct ProtoLoadRequest {
+    /// Path of the proto file. If `data` is set, only used in errors and as `PrefabInstance::source`.
     pub path: String,
-    pub result: ProtoLoadResult
+    /// Proto to spawn instead of reading `path`.
+    pub data: Option<Value>,
+    /// Put a `PrefabInstance` on the root once loaded, see `as_instance`.
+    pub instance: bool,
+    pub result: ProtoLoadResult,
+    pub progress: ProtoLoadProgress
 }
 
 impl ProtoLoadRequest {
 
+    /// The file is cached as `ProtoTemplate`, and only read again once it's modified.
     pub fn new(path: &str) -> Self {
         Self {
             path: path.to_string(),
-            result: Arc::new(Mutex::new(Poll::Pending))
+            data: None,
+            instance: false,
+            result: Arc::new(Mutex::new(Poll::Pending)),
+            progress: ProtoLoadProgress::default()
+        }
+    }
+
+    /// Spawns the proto as prefab instance, which can be despawned as a whole and reloaded
+    /// with `PrefabRequest`.
+    pub fn as_instance(mut self) -> Self {
+        self.instance = true;
+        self
+    }
+
+    /// Handle to watch the load after the request is pushed.
+    pub fn handle(&self) -> ProtoLoadHandle {
+        ProtoLoadHandle {
+            result: self.result.clone(),
+            progress: self.progress.clone()
         }
     }
 
+    /// Spawns the proto in `data`, `name` is used in place of a path.
+    pub fn from_value(name: &str, data: Value) -> Self {
+        Self {
+            data: Some(data),
+            ..Self::new(name)
+        }
+    }
+
+    pub fn from_template(res_mgr: &ResManager, template: &ResourceRef<ProtoTemplate>) -> Self {
+        let path = res_mgr.path_of(template).unwrap_or("<template>");
+        Self::from_value(path, res_mgr.get(template).data.clone())
+    }
+
+}
+
+/// Requests spawning `n` instances of the template. `init` is called with the index and the
+/// data of each instance before spawning, e.g. to set its position.
+///
+/// ```ignore
+/// let requests = spawn_many(&res_mgr, &bullet, 200, |i, proto| {
+///     proto[0]["Transform"]["pos"] = json!([i as f32, 0., 0.]);
+/// });
+/// load_requests.extend(requests);
+/// ```
+pub fn spawn_many(res_mgr: &ResManager, template: &ResourceRef<ProtoTemplate>, n: usize,
+                  mut init: impl FnMut(usize, &mut Value)) -> Vec<ProtoLoadRequest> {
+    (0..n)
+        .map(|i| {
+            let mut req = ProtoLoadRequest::from_template(res_mgr, template);
+            init(i, req.data.as_mut().unwrap());
+            req
+        })
+        .collect()
+}
+
+/// A parsed proto file, cached in `ResManager` by path.
+pub struct ProtoTemplate {
+    pub data: Value,
+    /// Modification time of the file when it was read.
+    modified: Option<SystemTime>
+}
+
+impl ProtoTemplate {
+
+    /// A template not read from a file, e.g. to add with a path that isn't on disk.
+    pub fn new(data: Value) -> Self {
+        Self {
+            data,
+            modified: None
+        }
+    }
+
+    /// Returns the cached template, or reads it. A cached template is read again if the file
+    /// was modified since.
+    pub fn load(res_mgr: &mut ResManager, path: &str) -> Result<ResourceRef<ProtoTemplate>, ProtoErrorKind> {
+        let modified = file_modified(path);
+        if let Some(ret) = res_mgr.get_by_path::<ProtoTemplate>(path) {
+            if modified.is_none() || res_mgr.get(&ret).modified == modified {
+                return Ok(ret)
+            }
+            res_mgr.replace(&ret, ProtoTemplate { data: read_proto_file(path)?, modified });
+            return Ok(ret)
+        }
+        let data = read_proto_file(path)?;
+        Ok(res_mgr.add_with_path(ProtoTemplate { data, modified }, path))
+    }
+
+    /// Reads the file again if the template is cached, also if it wasn't modified.
+    pub fn reload(res_mgr: &mut ResManager, path: &str) -> Result<(), ProtoErrorKind> {
+        if let Some(template) = res_mgr.get_by_path::<ProtoTemplate>(path) {
+            let modified = file_modified(path);
+            let data = read_proto_file(path)?;
+            res_mgr.replace(&template, ProtoTemplate { data, modified });
+        }
+        Ok(())
+    }
+
+}
+
+fn file_modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(asset::get_fs_path(path)).and_then(|x| x.modified()).ok()
+}
+
+impl LoadableAsset for ProtoTemplate {
+    fn read(path: &str) -> io::Result<Self> {
+        let modified = file_modified(path);
+        read_proto_file(path)
+            .map(|data| ProtoTemplate { data, modified })
+            .map_err(|e| match e {
+                ProtoErrorKind::Io(e) => e,
+                e => io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+            })
+    }
+}
+
+fn read_proto_file(path: &str) -> Result<Value, ProtoErrorKind> {
+    let bytes: Vec<u8> = asset::load_asset(path)?;
+    ProtoFormat::from_path(path).decode(&bytes)
+}
+
+/// Replaces the file atomically, so a failed write keeps the old one.
+fn write_proto_file(path: &str, proto: &Value) -> Result<(), ProtoErrorKind> {
+    let bytes = ProtoFormat::from_path(path).encode(proto)?;
+    asset::write_atomic(&asset::get_fs_path(path), &bytes)?;
+    Ok(())
 }
 
 pub type ProtoLoadRequests = Vec<ProtoLoadRequest>;
@@ -39,6 +183,10 @@ pub type ProtoLoadRequests = Vec<ProtoLoadRequest>;
 pub struct ProtoStoreRequest {
     pub entities: Vec<Entity>,
     pub target_path: String,
+    /// Only sets `result` instead of writing the proto to `target_path`.
+    pub to_value: bool,
+    /// The stored proto once it's written, or the error writing it.
+    pub result: ProtoStoreResult
 }
 
 impl ProtoStoreRequest {
@@ -46,20 +194,399 @@ impl ProtoStoreRequest {
     pub fn new(entities: &[Entity], target_path: &str) -> Self {
         Self {
             entities: entities.iter().map(|x| *x).collect(),
-            target_path: target_path.to_string()
+            target_path: target_path.to_string(),
+            to_value: false,
+            result: Arc::new(Mutex::new(Poll::Pending))
+        }
+    }
+
+    /// Stores the entities into `result` instead of a file.
+    pub fn to_value(entities: &[Entity]) -> Self {
+        Self {
+            to_value: true,
+            ..Self::new(entities, "")
         }
     }
 
 }
 
+pub type ProtoStoreResult = Arc<Mutex<Poll<Result<Value, ProtoError>>>>;
+
 pub type ProtoStoreRequests = Vec<ProtoStoreRequest>;
 
-pub type ProtoLoadResult = Arc<Mutex<Poll <Vec<Entity>> >>;
+pub type ProtoLoadResult = Arc<Mutex<Poll <Result<Vec<Entity>, ProtoError>> >>;
+
+/// Completed and total components of a proto load, and asset loads they depend on. Totals are
+/// known once `ProtoLoadSystem` picks up the request. Clones share the counts.
+#[derive(Clone, Default)]
+pub struct ProtoLoadProgress(Arc<ProgressCounts>);
+
+#[derive(Default)]
+struct ProgressCounts {
+    components: AtomicUsize,
+    components_done: AtomicUsize,
+    assets: AtomicUsize,
+    assets_done: AtomicUsize
+}
+
+impl ProtoLoadProgress {
+
+    /// (completed, total) components.
+    pub fn components(&self) -> (usize, usize) {
+        (self.0.components_done.load(Ordering::Relaxed), self.0.components.load(Ordering::Relaxed))
+    }
+
+    /// (completed, total) asset loads.
+    pub fn assets(&self) -> (usize, usize) {
+        (self.0.assets_done.load(Ordering::Relaxed), self.0.assets.load(Ordering::Relaxed))
+    }
+
+    /// Components and assets completed so far, each counting as one step. 0 before the totals are known.
+    pub fn fraction(&self) -> f32 {
+        let (components_done, components) = self.components();
+        let (assets_done, assets) = self.assets();
+        let total = components + assets;
+        if total == 0 {
+            0.
+        } else {
+            (components_done + assets_done) as f32 / total as f32
+        }
+    }
+
+    /// Reports an asset load, e.g. a sprite sheet read by a component. It completes when the
+    /// returned `AssetLoadTicket` is dropped, so move it into the load future.
+    pub fn start_asset(&self) -> AssetLoadTicket {
+        self.0.assets.fetch_add(1, Ordering::Relaxed);
+        AssetLoadTicket(self.clone())
+    }
+
+    fn add_components(&self, n: usize) {
+        self.0.components.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn complete_component(&self) {
+        self.0.components_done.fetch_add(1, Ordering::Relaxed);
+    }
+
+}
+
+/// An asset load counted in `ProtoLoadProgress`, completed when dropped.
+pub struct AssetLoadTicket(ProtoLoadProgress);
+
+impl Drop for AssetLoadTicket {
+    fn drop(&mut self) {
+        (self.0).0.assets_done.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The result and progress of a `ProtoLoadRequest`, see `ProtoLoadRequest::handle`.
+#[derive(Clone)]
+pub struct ProtoLoadHandle {
+    pub result: ProtoLoadResult,
+    pub progress: ProtoLoadProgress
+}
+
+impl ProtoLoadHandle {
+
+    pub fn is_finished(&self) -> bool {
+        self.result.lock().unwrap().is_ready()
+    }
+
+    /// From 0 to 1, 1 once the result is set, also if loading failed.
+    pub fn fraction(&self) -> f32 {
+        if self.is_finished() {
+            1.
+        } else {
+            self.progress.fraction()
+        }
+    }
+
+}
+
+/// Several proto loads tracked as one, e.g. everything a level needs behind a loading screen.
+///
+/// ```ignore
+/// let mut batch = LoadingBatch::new();
+/// for path in &["level.json", "player.json"] {
+///     load_requests.push(batch.add(ProtoLoadRequest::new(path)));
+/// }
+/// // Each frame
+/// draw_loading_bar(batch.fraction());
+/// if let Some(results) = batch.poll_finished() { ... }
+/// ```
+///
+/// Instead of polling, the batch can be pushed to `LoadingBatches` to get a
+/// `LoadingBatchFinished` event.
+///
+/// The batch owns the results of its requests: `poll_finished` moves them out, leaving each
+/// request's `result` `Pending`, so they shouldn't also be waited for elsewhere.
+#[derive(Default)]
+pub struct LoadingBatch {
+    handles: Vec<ProtoLoadHandle>,
+    finished: bool
+}
+
+impl LoadingBatch {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the request to the batch and returns it, to be pushed to `ProtoLoadRequests`.
+    pub fn add(&mut self, req: ProtoLoadRequest) -> ProtoLoadRequest {
+        self.handles.push(req.handle());
+        req
+    }
+
+    /// Mean of the requests' `ProtoLoadHandle::fraction`, 1 for an empty batch.
+    pub fn fraction(&self) -> f32 {
+        if self.finished || self.handles.is_empty() {
+            return 1.
+        }
+        self.handles.iter().map(|x| x.fraction()).sum::<f32>() / self.handles.len() as f32
+    }
+
+    pub fn is_finished(&self) -> bool {
+        // Results are taken out by `poll_finished`
+        self.finished || self.handles.iter().all(|x| x.is_finished())
+    }
+
+    /// Once all requests finished, returns their results in the order they were added. Only
+    /// returns them once, later calls and calls before that return `None`. Takes the results out
+    /// of the requests, see `LoadingBatch`.
+    pub fn poll_finished(&mut self) -> Option<Vec<Result<Vec<Entity>, ProtoError>>> {
+        if self.finished || !self.handles.iter().all(|x| x.is_finished()) {
+            return None
+        }
+        self.finished = true;
+        Some(self.handles.iter()
+            .map(|x| match std::mem::replace(&mut *x.result.lock().unwrap(), Poll::Pending) {
+                Poll::Ready(result) => result,
+                Poll::Pending => unreachable!()
+            })
+            .collect())
+    }
+
+}
+
+/// `LoadingBatch`es watched by `LoadingBatchSystem`, by name. Each is removed once finished, and
+/// its results sent to the `EventChannel<LoadingBatchFinished>` resource.
+pub type LoadingBatches = Vec<(String, LoadingBatch)>;
+
+/// Sent once for each batch pushed to `LoadingBatches`, see `LoadingBatch::poll_finished`.
+pub struct LoadingBatchFinished {
+    pub name: String,
+    pub results: Vec<Result<Vec<Entity>, ProtoError>>
+}
+
+#[derive(Debug)]
+pub enum ProtoErrorKind {
+    Io(io::Error),
+    /// Malformed JSON, or data that doesn't match the component.
+    Json(serde_json::Error),
+    Invalid(String),
+    /// Malformed binary proto.
+    Binary(serde_cbor::Error),
+    /// Migration of component data to the current version failed.
+    Migration { component: String, from_version: u32, error: Box<ProtoErrorKind> },
+    /// Chain of proto files referencing each other with `$proto`.
+    CyclicReference(Vec<String>)
+}
+
+impl From<io::Error> for ProtoErrorKind {
+    fn from(e: io::Error) -> Self {
+        ProtoErrorKind::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ProtoErrorKind {
+    fn from(e: serde_json::Error) -> Self {
+        ProtoErrorKind::Json(e)
+    }
+}
+
+impl From<serde_cbor::Error> for ProtoErrorKind {
+    fn from(e: serde_cbor::Error) -> Self {
+        ProtoErrorKind::Binary(e)
+    }
+}
+
+impl fmt::Display for ProtoErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtoErrorKind::Io(e) => write!(f, "{}", e),
+            // Line is 0 for errors of data not parsed from text
+            ProtoErrorKind::Json(e) if e.line() == 0 => write!(f, "{}", e),
+            ProtoErrorKind::Json(e) => write!(f, "{} (line {}, column {})", e, e.line(), e.column()),
+            ProtoErrorKind::Invalid(msg) => write!(f, "{}", msg),
+            ProtoErrorKind::Binary(e) => write!(f, "{}", e),
+            ProtoErrorKind::Migration { component, from_version, error } =>
+                write!(f, "Failed to migrate {} from version {}: {}", component, from_version, error),
+            ProtoErrorKind::CyclicReference(chain) => write!(f, "Cyclic proto reference: {}", chain.join(" -> "))
+        }
+    }
+}
+
+/// Reason a `ProtoLoadRequest` failed. All entities created by the request are deleted.
+#[derive(Debug)]
+pub struct ProtoError {
+    /// The proto file containing the error, which may be one nested with `$proto`.
+    pub path: String,
+    /// Index of the entity in the file.
+    pub entity_idx: Option<usize>,
+    /// `Name` of the entity in the file, if it has one.
+    pub entity_name: Option<Box<str>>,
+    pub component: Option<String>,
+    pub kind: ProtoErrorKind
+}
+
+impl ProtoError {
+
+    pub fn new(path: &str, kind: ProtoErrorKind) -> Self {
+        Self {
+            path: path.to_string(),
+            entity_idx: None,
+            entity_name: None,
+            component: None,
+            kind
+        }
+    }
+
+    pub fn with_entity(mut self, entity_idx: usize) -> Self {
+        self.entity_idx = Some(entity_idx);
+        self
+    }
+
+    pub fn with_entity_name(mut self, name: Option<&str>) -> Self {
+        self.entity_name = name.map(Box::from);
+        self
+    }
+
+    pub fn with_component(mut self, component: &str) -> Self {
+        self.component = Some(component.to_string());
+        self
+    }
+
+}
+
+impl fmt::Display for ProtoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path)?;
+        if let Some(idx) = self.entity_idx {
+            write!(f, ", entity #{}", idx)?;
+        }
+        if let Some(name) = &self.entity_name {
+            write!(f, " ({})", name)?;
+        }
+        if let Some(component) = &self.component {
+            write!(f, ", component {}", component)?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl std::error::Error for ProtoError {}
+
+/// The `Name` in the data of a proto entity, for error messages.
+fn proto_entity_name(m: &serde_json::Map<String, Value>) -> Option<&str> {
+    m.get("Name").and_then(|x| x.as_str())
+}
+
+/// Extension of protos stored in the binary format, e.g. `level.bproto`.
+pub const BINARY_PROTO_EXT: &str = "bproto";
+
+/// Leading bytes of a binary proto, the last one is the format version.
+const BINARY_PROTO_MAGIC: &[u8] = b"MUP\x01";
+
+/// Encoding of a proto file, selected by its extension.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProtoFormat {
+    Json,
+    /// CBOR, with component names stored once in a table and referenced by index.
+    Binary
+}
+
+/// Binary form of a proto.
+#[derive(Serialize, Deserialize)]
+struct BinaryProto {
+    names: Vec<String>,
+    /// Per entity, (index into `names`, component data).
+    entities: Vec<Vec<(u32, Value)>>
+}
+
+impl ProtoFormat {
+
+    pub fn from_path(path: &str) -> Self {
+        match std::path::Path::new(path).extension() {
+            Some(ext) if ext == BINARY_PROTO_EXT => ProtoFormat::Binary,
+            _ => ProtoFormat::Json
+        }
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> Result<Value, ProtoErrorKind> {
+        match self {
+            ProtoFormat::Json => Ok(serde_json::from_slice(bytes)?),
+            ProtoFormat::Binary => {
+                if !bytes.starts_with(BINARY_PROTO_MAGIC) {
+                    return Err(ProtoErrorKind::Invalid("Not a binary proto, or unsupported version".to_string()))
+                }
+                let BinaryProto { names, entities } = serde_cbor::from_slice(&bytes[BINARY_PROTO_MAGIC.len()..])?;
+                let entities = entities.into_iter()
+                    .map(|components| {
+                        components.into_iter()
+                            .map(|(ix, data)| match names.get(ix as usize) {
+                                Some(name) => Ok((name.clone(), data)),
+                                None => Err(ProtoErrorKind::Invalid(format!("Component name index {} out of range", ix)))
+                            })
+                            .collect::<Result<serde_json::Map<_, _>, _>>()
+                            .map(Value::Object)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Value::Array(entities))
+            }
+        }
+    }
+
+    /// `proto` must be an array of entity objects.
+    pub fn encode(self, proto: &Value) -> Result<Vec<u8>, ProtoErrorKind> {
+        match self {
+            ProtoFormat::Json => Ok(serde_json::to_vec_pretty(proto)?),
+            ProtoFormat::Binary => {
+                let invalid = || ProtoErrorKind::Invalid("Invalid proto, expecting array of objects".to_string());
+                let mut names: Vec<String> = vec![];
+                let mut name_ixs: HashMap<&str, u32> = HashMap::new();
+                let mut entities = vec![];
+                for entity in proto.as_array().ok_or_else(invalid)? {
+                    let components = entity.as_object().ok_or_else(invalid)?.iter()
+                        .map(|(name, data)| {
+                            let ix = *name_ixs.entry(name).or_insert_with(|| {
+                                names.push(name.clone());
+                                names.len() as u32 - 1
+                            });
+                            (ix, data.clone())
+                        })
+                        .collect();
+                    entities.push(components);
+                }
+
+                let mut ret = BINARY_PROTO_MAGIC.to_vec();
+                serde_cbor::to_writer(&mut ret, &BinaryProto { names, entities })?;
+                Ok(ret)
+            }
+        }
+    }
+
+}
+
+pub type ComponentLoadFuture<T> = Pin<Box<dyn Future<Output = Result<T, ProtoErrorKind>> + Send + Sync>>;
 
 pub struct ComponentLoadArgs<'a> {
     pub data: Value,
     pub entity_idx: usize,
-    pub all_entity_vec: &'a Vec<Entity>
+    /// Shared by all components loaded with the same scope, clone the `Arc` to keep it in a future.
+    pub all_entity_vec: &'a Arc<[Entity]>,
+    /// Report asset loads of the component here, see `ProtoLoadProgress::start_asset`.
+    pub progress: &'a ProtoLoadProgress
 }
 
 impl<'a> ComponentLoadArgs<'a> {
@@ -68,7 +595,8 @@ impl<'a> ComponentLoadArgs<'a> {
         Self {
             data: new_data,
             entity_idx: self.entity_idx,
-            all_entity_vec: self.all_entity_vec
+            all_entity_vec: self.all_entity_vec,
+            progress: self.progress
         }
     }
 
@@ -88,80 +616,995 @@ impl<'a, T: Component> ComponentStoreArgs<'a, T> {
             all_entity_vec: self.all_entity_vec
         }
     }
-}
+}
+
+pub trait ComponentS11n<'a> {
+    type SystemData: SystemData<'a>;
+    type StoreSystemData: SystemData<'a>;
+    type Output: Component + Send + Sync;
+    /// Result of the `load_async` future, turned into `Output` by `integrate`.
+    type Loaded: Send + 'static;
+
+    /// Starts loading the component. The future is run on the proto thread pool, so it should
+    /// do heavy work like file reads and decoding, but can't access the world.
+    ///
+    /// Errors can be returned directly or from the future, both fail the whole load request.
+    fn load_async(&mut self, ctx: ComponentLoadArgs, system_data: &mut Self::SystemData) -> Result<ComponentLoadFuture<Self::Loaded>, ProtoErrorKind>;
+
+    /// Called on the system's thread once all components of the proto are loaded.
+    fn integrate(&mut self, loaded: Self::Loaded, system_data: &mut Self::SystemData) -> Result<Self::Output, ProtoErrorKind>;
+
+    /// Returning `None` omits the component, e.g. when it references an entity that isn't
+    /// stored. `Some(Value::Null)` is stored as is, e.g. for unit structs.
+    fn store(&mut self, ctx: ComponentStoreArgs<Self::Output>, system_data: &mut Self::StoreSystemData) -> Option<Value>;
+
+    /// Get type name literal used in json representation.
+    /// We can use std::any::type_name, but that has no stability guarantee.
+    fn type_name(&self) -> &'static str;
+
+    /// Version of the data format returned by `store`. When changing the format, bump it and
+    /// register a migration from the previous version with `InitContextProtoExt::add_proto_migration`.
+    fn version(&self) -> u32 {
+        1
+    }
+
+    /// JSON Schema of the data returned by `store`, used to validate protos and for editor
+    /// autocompletion. Usually created with `schema_for`.
+    fn schema(&self) -> Option<Value> {
+        None
+    }
+}
+
+/// Standalone JSON Schema of `T`, with all subschemas inlined.
+pub fn schema_for<T: JsonSchema>() -> Value {
+    let settings = schemars::gen::SchemaSettings::draft07().with(|s| {
+        s.inline_subschemas = true;
+        s.meta_schema = None;
+    });
+    let schema = settings.into_generator().into_root_schema_for::<T>();
+    serde_json::to_value(schema).expect("Serialize schema failed")
+}
+
+/// Schema of an object with the given `(name, schema, required)` fields.
+pub fn object_schema(fields: Vec<(&str, Value, bool)>) -> Value {
+    let required: Vec<&str> = fields.iter().filter(|x| x.2).map(|x| x.0).collect();
+    let properties: serde_json::Map<String, Value> = fields.into_iter()
+        .map(|(name, schema, _)| (name.to_string(), schema))
+        .collect();
+    serde_json::json!({
+        "type": "object",
+        "properties": properties,
+        "required": required
+    })
+}
+
+/// `ComponentS11n` using the serde implementation of `T`, deserializing on the proto thread pool.
+///
+/// `asset_ref` fields need `with_assets`, which loads them on the system's thread.
+#[derive(Clone)]
+pub struct ComponentS11nDefault<T>
+    where T: Send + Sync + Serialize + DeserializeOwned {
+    name: &'static str,
+    schema: Option<Value>,
+    marker: PhantomData<T>
+}
+
+impl<T> ComponentS11nDefault<T>
+    where T: Send + Sync + Serialize + DeserializeOwned {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            schema: None,
+            marker: PhantomData
+        }
+    }
+
+    /// Provides the schema of `T` as `ComponentS11n::schema`.
+    pub fn with_schema(mut self) -> Self where T: JsonSchema {
+        self.schema = Some(schema_for::<T>());
+        self
+    }
+
+    /// Deserializes in `integrate` instead, with `asset_ref` fields loading their assets.
+    /// This needs write access to the `ResManager`, so these loads don't run in parallel.
+    pub fn with_assets(self) -> ComponentS11nWithAssets<T> {
+        ComponentS11nWithAssets(self)
+    }
+}
+
+/// Serializes `component` with the proto's entities and assets in context. A component that
+/// fails, e.g. because of an asset not loaded from a path, is omitted with a warning.
+fn store_default<T: Serialize>(name: &str, component: &T, entities: &[Entity], res_mgr: &ResManager) -> Option<Value> {
+    let result = with_proto_entities(entities, || {
+        asset_ref::with_res_mgr(res_mgr, || serde_json::to_value(component))
+    });
+    result.map_err(|e| warn!("Can't store {}, omitting it: {}", name, e)).ok()
+}
+
+impl<'a, T> ComponentS11n<'a> for ComponentS11nDefault<T>
+    where T: Component + Send + Sync + Serialize + DeserializeOwned
+{
+    type SystemData = ();
+    type StoreSystemData = ReadExpect<'a, ResManager>;
+    type Output = T;
+    type Loaded = T;
+
+    fn load_async(&mut self, ctx: ComponentLoadArgs, _: &mut Self::SystemData)
+        -> Result<ComponentLoadFuture<Self::Loaded>, ProtoErrorKind> {
+        let ComponentLoadArgs { data, all_entity_vec, .. } = ctx;
+        let entities = Arc::clone(all_entity_vec);
+        Ok(Box::pin(async move {
+            Ok(with_proto_entities(&entities, || serde_json::from_value(data))?)
+        }))
+    }
+
+    fn integrate(&mut self, loaded: Self::Loaded, _: &mut Self::SystemData) -> Result<Self::Output, ProtoErrorKind> {
+        Ok(loaded)
+    }
+
+    fn store(&mut self, ctx: ComponentStoreArgs<Self::Output>, res_mgr: &mut Self::StoreSystemData)
+        -> Option<Value> {
+        store_default(self.name, ctx.component, ctx.all_entity_vec, &*res_mgr)
+    }
+
+    fn type_name(&self) -> &'static str {
+        self.name
+    }
+
+    fn schema(&self) -> Option<Value> {
+        self.schema.clone()
+    }
+}
+
+/// `ComponentS11nDefault` of a component with `asset_ref` fields, see `ComponentS11nDefault::with_assets`.
+#[derive(Clone)]
+pub struct ComponentS11nWithAssets<T>(ComponentS11nDefault<T>)
+    where T: Send + Sync + Serialize + DeserializeOwned;
+
+impl<'a, T> ComponentS11n<'a> for ComponentS11nWithAssets<T>
+    where T: Component + Send + Sync + Serialize + DeserializeOwned
+{
+    type SystemData = WriteExpect<'a, ResManager>;
+    type StoreSystemData = ReadExpect<'a, ResManager>;
+    type Output = T;
+    type Loaded = (Value, Arc<[Entity]>);
+
+    fn load_async(&mut self, ctx: ComponentLoadArgs, _: &mut Self::SystemData)
+        -> Result<ComponentLoadFuture<Self::Loaded>, ProtoErrorKind> {
+        let loaded = (ctx.data, Arc::clone(ctx.all_entity_vec));
+        Ok(Box::pin(async move { Ok(loaded) }))
+    }
+
+    fn integrate(&mut self, (data, entities): Self::Loaded, res_mgr: &mut Self::SystemData) -> Result<Self::Output, ProtoErrorKind> {
+        Ok(with_proto_entities(&entities, || {
+            asset_ref::with_res_mgr_mut(&mut *res_mgr, || serde_json::from_value(data))
+        })?)
+    }
+
+    fn store(&mut self, ctx: ComponentStoreArgs<Self::Output>, res_mgr: &mut Self::StoreSystemData)
+        -> Option<Value> {
+        store_default(self.0.name, ctx.component, ctx.all_entity_vec, &*res_mgr)
+    }
+
+    fn type_name(&self) -> &'static str {
+        self.0.name
+    }
+
+    fn schema(&self) -> Option<Value> {
+        self.0.schema.clone()
+    }
+}
+
+pub use mu_derive::ProtoComponent;
+
+/// A component with its serialization generated by `#[derive(ProtoComponent)]`.
+pub trait ProtoComponent: Component + Send + Sync + Sized {
+    /// Name used in json representation, see `ComponentS11n::type_name`.
+    const PROTO_NAME: &'static str;
+    /// See `ComponentS11n::version`.
+    const PROTO_VERSION: u32 = 1;
+
+    fn load(data: Value, ctx: &mut ProtoLoadCtx) -> Result<Self, ProtoErrorKind>;
+
+    /// An error omits the component with a warning, e.g. for an asset not loaded from a path.
+    fn store(&self, ctx: &ProtoStoreCtx) -> Result<Value, ProtoErrorKind>;
+
+    /// See `ComponentS11n::schema`.
+    fn schema() -> Option<Value> {
+        None
+    }
+}
+
+/// A field stored as index into the entities of the proto.
+pub trait EntityField: Sized {
+    fn load(data: Value, entities: &[Entity]) -> Result<Self, ProtoErrorKind>;
+
+    fn store(&self, entities: &[Entity]) -> Value;
+
+    fn schema() -> Value;
+}
+
+/// Index of `entity` in the stored entities, or `null` if it isn't stored.
+fn entity_index(entity: Entity, entities: &[Entity]) -> Value {
+    match entities.iter().position(|x| *x == entity) {
+        Some(ix) => ix.into(),
+        None => {
+            // No `NameIndex` here, so without the name
+            warn!("Referenced entity {} isn't stored, writing null", entity_label(entity, None));
+            Value::Null
+        }
+    }
+}
+
+impl EntityField for Entity {
+    fn load(data: Value, entities: &[Entity]) -> Result<Self, ProtoErrorKind> {
+        let ix: usize = serde_json::from_value(data)?;
+        entities.get(ix)
+            .copied()
+            .ok_or_else(|| ProtoErrorKind::Invalid(format!("Entity index {} out of range", ix)))
+    }
+
+    fn store(&self, entities: &[Entity]) -> Value {
+        entity_index(*self, entities)
+    }
+
+    fn schema() -> Value {
+        serde_json::json!({ "type": "integer", "minimum": 0 })
+    }
+}
+
+impl EntityField for Option<Entity> {
+    fn load(data: Value, entities: &[Entity]) -> Result<Self, ProtoErrorKind> {
+        if data.is_null() {
+            Ok(None)
+        } else {
+            Entity::load(data, entities).map(Some)
+        }
+    }
+
+    fn store(&self, entities: &[Entity]) -> Value {
+        match self {
+            Some(e) => e.store(entities),
+            None => Value::Null
+        }
+    }
+
+    fn schema() -> Value {
+        serde_json::json!({ "type": ["integer", "null"], "minimum": 0 })
+    }
+}
+
+thread_local! {
+    static CURRENT_ENTITIES: Cell<Option<*const [Entity]>> = const { Cell::new(None) };
+}
+
+/// Restores the previous entities, also when unwinding.
+struct EntitiesScopeGuard(Option<*const [Entity]>);
+
+impl Drop for EntitiesScopeGuard {
+    fn drop(&mut self) {
+        CURRENT_ENTITIES.with(|x| x.set(self.0));
+    }
+}
+
+/// Makes the proto's entities available to `EntityRef` (de)serialization inside `f`.
+/// `ComponentS11nDefault`, `ProtoLoadCtx::read_field` and `ProtoStoreCtx::write_field` already do this.
+pub fn with_proto_entities<R>(entities: &[Entity], f: impl FnOnce() -> R) -> R {
+    let _guard = EntitiesScopeGuard(CURRENT_ENTITIES.with(|x| x.replace(Some(entities))));
+    f()
+}
+
+fn with_current_entities<R>(f: impl FnOnce(Option<&[Entity]>) -> R) -> R {
+    // Safety: the pointer is only set while the borrow in `with_proto_entities` is alive
+    let entities = CURRENT_ENTITIES.with(|x| x.get()).map(|ptr| unsafe { &*ptr });
+    f(entities)
+}
+
+/// An entity referenced from a serde type, stored as index into the entities of the proto.
+///
+/// Works anywhere in the data, e.g. `Vec<EntityRef>` or `Option<EntityRef>`. An entity that
+/// isn't stored is written as `null`, so use `Option<EntityRef>` if that can happen.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EntityRef(pub Entity);
+
+impl From<Entity> for EntityRef {
+    fn from(e: Entity) -> Self {
+        EntityRef(e)
+    }
+}
+
+impl Serialize for EntityRef {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+        with_current_entities(|entities| match entities {
+            Some(entities) => entity_index(self.0, entities).serialize(serializer),
+            None => Err(S::Error::custom("EntityRef serialized outside of with_proto_entities"))
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for EntityRef {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let ix = usize::deserialize(deserializer)?;
+        with_current_entities(|entities| match entities {
+            Some(entities) => entities.get(ix)
+                .map(|e| EntityRef(*e))
+                .ok_or_else(|| D::Error::custom(format!("Entity index {} out of range", ix))),
+            None => Err(D::Error::custom("EntityRef deserialized outside of with_proto_entities"))
+        })
+    }
+}
+
+impl JsonSchema for EntityRef {
+    fn schema_name() -> String {
+        "EntityRef".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        usize::json_schema(gen)
+    }
+}
+
+/// Helpers used by `ProtoComponent::load`.
+pub struct ProtoLoadCtx<'p> {
+    pub entities: &'p [Entity],
+    pub res_mgr: &'p mut ResManager
+}
+
+impl<'p> ProtoLoadCtx<'p> {
+
+    pub fn fields(data: Value) -> Result<serde_json::Map<String, Value>, ProtoErrorKind> {
+        match data {
+            Value::Object(m) => Ok(m),
+            _ => Err(ProtoErrorKind::Invalid("Invalid component data, expecting object".to_string()))
+        }
+    }
+
+    /// Missing fields are read as `null`, so `Option`s can be omitted.
+    fn take_field(fields: &mut serde_json::Map<String, Value>, name: &str) -> Value {
+        fields.remove(name).unwrap_or(Value::Null)
+    }
+
+    fn field_error(name: &str, e: ProtoErrorKind) -> ProtoErrorKind {
+        ProtoErrorKind::Invalid(format!("Field {}: {}", name, e))
+    }
+
+    pub fn read_field<T: DeserializeOwned>(&mut self, fields: &mut serde_json::Map<String, Value>, name: &str)
+        -> Result<T, ProtoErrorKind> {
+        let data = Self::take_field(fields, name);
+        let res_mgr = &mut *self.res_mgr;
+        with_proto_entities(self.entities, || asset_ref::with_res_mgr_mut(res_mgr, || serde_json::from_value(data)))
+            .map_err(|e| Self::field_error(name, e.into()))
+    }
+
+    pub fn read_entity<T: EntityField>(&mut self, fields: &mut serde_json::Map<String, Value>, name: &str)
+        -> Result<T, ProtoErrorKind> {
+        T::load(Self::take_field(fields, name), self.entities)
+            .map_err(|e| Self::field_error(name, e))
+    }
+
+    /// Loads the asset if it isn't loaded yet.
+    pub fn read_resource<T: 'static + LoadableAsset + Send + Sync>(&mut self, fields: &mut serde_json::Map<String, Value>, name: &str)
+        -> Result<ResourceRef<T>, ProtoErrorKind> {
+        let path: String = self.read_field(fields, name)?;
+        self.res_mgr.load_asset(&path)
+            .map_err(|e| Self::field_error(name, e.into()))
+    }
+
+}
+
+/// Helpers used by `ProtoComponent::store`.
+pub struct ProtoStoreCtx<'p> {
+    pub entities: &'p [Entity],
+    pub res_mgr: &'p ResManager
+}
+
+impl<'p> ProtoStoreCtx<'p> {
+
+    pub fn write_field<T: Serialize>(&self, fields: &mut serde_json::Map<String, Value>, name: &str, value: &T)
+        -> Result<(), ProtoErrorKind> {
+        let value = with_proto_entities(self.entities, || {
+            asset_ref::with_res_mgr(self.res_mgr, || serde_json::to_value(value))
+        }).map_err(|e| ProtoLoadCtx::field_error(name, e.into()))?;
+        fields.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    pub fn write_entity<T: EntityField>(&self, fields: &mut serde_json::Map<String, Value>, name: &str, value: &T) {
+        fields.insert(name.to_string(), value.store(self.entities));
+    }
+
+    pub fn write_resource<T: 'static + Send + Sync>(&self, fields: &mut serde_json::Map<String, Value>, name: &str, value: &ResourceRef<T>)
+        -> Result<(), ProtoErrorKind> {
+        let path = self.res_mgr.path_of(value)
+            .ok_or_else(|| ProtoLoadCtx::field_error(name,
+                ProtoErrorKind::Invalid(format!("{:?} isn't loaded from path", value))))?;
+        fields.insert(name.to_string(), path.into());
+        Ok(())
+    }
+
+}
+
+/// Upgrades data of `version` to `version + 1`.
+pub type ProtoMigration = Box<dyn Fn(Value) -> Result<Value, ProtoErrorKind> + Send + Sync>;
+
+/// Key of an entity's object listing the versions of its components, omitted for version 1.
+///
+/// ```json
+/// { "$versions": { "Door": 2 }, "Door": { ... }, "Transform": { ... } }
+/// ```
+///
+/// In a `$proto` entry, it holds versions of the components in `overrides`.
+pub const PROTO_VERSIONS_KEY: &str = "$versions";
+
+/// Current versions of the registered components, and migrations between versions.
+///
+/// Migrations run when loading a proto, before `ComponentS11n::load_async`. They also run on
+/// the partial data of `$proto` overrides, so they should keep missing fields missing.
+#[derive(Default)]
+pub struct ProtoMigrations {
+    versions: HashMap<String, u32>,
+    migrations: HashMap<(String, u32), ProtoMigration>
+}
+
+impl ProtoMigrations {
+
+    /// Versions and migrations of the engine's built-in components, for use without a `Runtime`.
+    pub fn builtin() -> Self {
+        let mut ret = Self::default();
+        register_engine_components(&mut ret);
+        ret
+    }
+
+    /// Records the current version of the component.
+    pub fn register<T: for<'a> ComponentS11n<'a>>(&mut self, s11n: &T) {
+        self.set_version(s11n.type_name(), s11n.version());
+    }
+
+    pub fn set_version(&mut self, component: &str, version: u32) {
+        self.versions.insert(component.to_string(), version);
+    }
+
+    /// Current version of the component, `None` if it isn't registered.
+    pub fn version(&self, component: &str) -> Option<u32> {
+        self.versions.get(component).copied()
+    }
+
+    pub fn add<F>(&mut self, component: &str, from_version: u32, migration: F)
+        where F: Fn(Value) -> Result<Value, ProtoErrorKind> + Send + Sync + 'static {
+        self.migrations.insert((component.to_string(), from_version), Box::new(migration));
+    }
+
+    /// Runs the migrations of `component` from `from_version` up to `to_version`.
+    pub fn migrate(&self, component: &str, from_version: u32, to_version: u32, mut data: Value) -> Result<Value, ProtoErrorKind> {
+        if from_version > to_version {
+            return Err(ProtoErrorKind::Invalid(format!("{} version {} is newer than supported version {}",
+                                                       component, from_version, to_version)))
+        }
+        for version in from_version..to_version {
+            let migration = self.migrations.get(&(component.to_string(), version))
+                .ok_or_else(|| ProtoErrorKind::Invalid(format!("No migration of {} from version {}", component, version)))?;
+            data = migration(data).map_err(|e| ProtoErrorKind::Migration {
+                component: component.to_string(),
+                from_version: version,
+                error: Box::new(e)
+            })?;
+        }
+        Ok(data)
+    }
+
+    /// `$versions` object of an entity with the given components, `None` if all are version 1.
+    pub fn versions_value<'n>(&self, components: impl Iterator<Item = &'n str>) -> Option<Value> {
+        let versions: serde_json::Map<String, Value> = components
+            .filter_map(|name| self.version(name).filter(|v| *v > 1).map(|v| (name.to_string(), v.into())))
+            .collect();
+        if versions.is_empty() {
+            None
+        } else {
+            Some(Value::Object(versions))
+        }
+    }
+
+    /// Migrates the components of an entity object to their current versions and updates its
+    /// `$versions`. Components that aren't registered are kept as is. Returns whether the
+    /// entity changed.
+    pub fn upgrade_entity(&self, entity: &mut serde_json::Map<String, Value>) -> Result<bool, ProtoErrorKind> {
+        let old_versions = entity.remove(PROTO_VERSIONS_KEY);
+        let versions: HashMap<String, u32> = match &old_versions {
+            Some(v) => serde_json::from_value(v.clone())?,
+            None => HashMap::new()
+        };
+
+        let components = if entity.contains_key(PROTO_REF_KEY) {
+            match entity.get_mut(PROTO_OVERRIDES_KEY) {
+                Some(Value::Object(overrides)) => Some(overrides),
+                _ => None
+            }
+        } else {
+            Some(&mut *entity)
+        };
+
+        let mut migrated = false;
+        let mut new_versions = serde_json::Map::new();
+        for (name, data) in components.into_iter().flatten() {
+            let from_version = versions.get(name).copied().unwrap_or(1);
+            let version = match self.version(name) {
+                Some(v) => v,
+                None => {
+                    // Not ours to migrate, keep its version as is
+                    if versions.contains_key(name) {
+                        new_versions.insert(name.clone(), from_version.into());
+                    }
+                    continue
+                }
+            };
+
+            if from_version != version && !data.is_null() {
+                *data = self.migrate(name, from_version, version, data.take())?;
+                migrated = true;
+            }
+            if version > 1 {
+                new_versions.insert(name.clone(), version.into());
+            }
+        }
+
+        let new_versions = if new_versions.is_empty() { None } else { Some(Value::Object(new_versions)) };
+        let changed = migrated || new_versions != old_versions;
+        if let Some(v) = new_versions {
+            entity.insert(PROTO_VERSIONS_KEY.to_string(), v);
+        }
+        Ok(changed)
+    }
+
+    /// Upgrades every proto file (`.json` or `.bproto`) under `dir` in place, returning paths
+    /// of the changed files.
+    ///
+    /// All files are migrated before any is written, so if a file can't be read or migrated no
+    /// file is changed and all the errors are returned. Each file is replaced atomically.
+    pub fn upgrade_dir(&self, dir: &std::path::Path) -> Result<Vec<String>, Vec<ProtoError>> {
+        let (files, mut errors) = read_proto_files(dir).map_err(|e| vec![e])?;
+
+        let mut changed_files = vec![];
+        for (path, format, mut proto) in files {
+            let mut changed = false;
+            for (idx, entity) in proto.as_array_mut().unwrap().iter_mut().enumerate() {
+                if let Value::Object(m) = entity {
+                    match self.upgrade_entity(m) {
+                        Ok(x) => changed |= x,
+                        Err(e) => errors.push(ProtoError::new(&path, e).with_entity(idx))
+                    }
+                }
+            }
+
+            if changed {
+                match format.encode(&proto) {
+                    Ok(bytes) => changed_files.push((path, bytes)),
+                    Err(e) => errors.push(ProtoError::new(&path, e))
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors)
+        }
+
+        let mut ret = vec![];
+        for (path, bytes) in changed_files {
+            match asset::write_atomic(std::path::Path::new(&path), &bytes) {
+                Ok(()) => ret.push(path),
+                Err(e) => errors.push(ProtoError::new(&path, e.into()))
+            }
+        }
+        if errors.is_empty() { Ok(ret) } else { Err(errors) }
+    }
+
+}
+
+type ProtoFile = (String, ProtoFormat, Value);
+
+/// Reads every proto file (`.json` or `.bproto`) under `dir`, skipping json files that aren't
+/// protos, i.e. not an array of objects. Files that can't be read or parsed are returned as
+/// errors, only failing to list `dir` fails the whole call.
+fn read_proto_files(dir: &std::path::Path) -> Result<(Vec<ProtoFile>, Vec<ProtoError>), ProtoError> {
+    let mut files = vec![];
+    collect_files(dir, &mut files).map_err(|e| ProtoError::new(&dir.to_string_lossy(), e.into()))?;
+
+    let mut ret = vec![];
+    let mut errors = vec![];
+    for file in files {
+        let path = file.to_string_lossy().to_string();
+        let format = match file.extension() {
+            Some(ext) if ext == "json" || ext == BINARY_PROTO_EXT => ProtoFormat::from_path(&path),
+            _ => continue
+        };
+
+        let proto = std::fs::read(&file)
+            .map_err(|e| e.into())
+            .and_then(|bytes| format.decode(&bytes));
+        match proto {
+            Ok(proto) => match proto.as_array() {
+                Some(entities) if entities.iter().all(|x| x.is_object()) => ret.push((path, format, proto)),
+                _ => continue
+            },
+            Err(e) => errors.push(ProtoError::new(&path, e))
+        }
+    }
+    Ok((ret, errors))
+}
+
+/// All files under `dir` recursively, sorted.
+fn collect_files(dir: &std::path::Path, out: &mut Vec<std::path::PathBuf>) -> io::Result<()> {
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|x| x.map(|x| x.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            collect_files(&path, out)?;
+        } else {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// JSON Schemas of the registered components, used to validate protos without loading them.
+#[derive(Default)]
+pub struct ProtoSchemas {
+    /// `None` for components without a schema, which accept any data.
+    components: BTreeMap<String, Option<Value>>
+}
+
+impl ProtoSchemas {
+
+    /// Schemas of the engine's built-in components, for use without a `Runtime`.
+    pub fn builtin() -> Self {
+        let mut ret = Self::default();
+        register_engine_components(&mut ret);
+        ret
+    }
+
+    pub fn register<T: for<'a> ComponentS11n<'a>>(&mut self, s11n: &T) {
+        self.components.insert(s11n.type_name().to_string(), s11n.schema());
+    }
+
+    /// Schema of a whole proto file, accepting only the registered components.
+    pub fn combined(&self) -> Value {
+        let names: Vec<&String> = self.components.keys().collect();
+        let mut properties = serde_json::Map::new();
+        properties.insert(PROTO_REF_KEY.to_string(), serde_json::json!({ "type": "string" }));
+        properties.insert(PROTO_OVERRIDES_KEY.to_string(), serde_json::json!({
+            "type": "object",
+            "propertyNames": { "enum": names }
+        }));
+        properties.insert(PROTO_VERSIONS_KEY.to_string(), serde_json::json!({
+            "type": "object",
+            "additionalProperties": { "type": "integer", "minimum": 1 }
+        }));
+        for (name, schema) in &self.components {
+            properties.insert(name.clone(), schema.clone().unwrap_or(Value::Bool(true)));
+        }
+
+        serde_json::json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "title": "Proto",
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": properties,
+                "additionalProperties": false
+            }
+        })
+    }
+
+    fn compile(&self) -> HashMap<&str, JSONSchema> {
+        self.components.iter()
+            .filter_map(|(name, schema)| schema.as_ref().map(|x| (name, x)))
+            .map(|(name, schema)| {
+                let compiled = JSONSchema::compile(schema)
+                    .unwrap_or_else(|e| panic!("Invalid schema of component {}: {}", name, e));
+                (name.as_str(), compiled)
+            })
+            .collect()
+    }
+
+    /// Checks the entities of a proto (as read from `path`) for unknown components and data not
+    /// matching the schemas. Overrides of `$proto` entries are partial, only their names are checked.
+    pub fn validate(&self, path: &str, proto: &Value) -> Vec<ProtoError> {
+        self.validate_with(&self.compile(), path, proto)
+    }
+
+    fn validate_with(&self, compiled: &HashMap<&str, JSONSchema>, path: &str, proto: &Value) -> Vec<ProtoError> {
+        let invalid = |msg: String| ProtoError::new(path, ProtoErrorKind::Invalid(msg));
+        let entities = match proto.as_array() {
+            Some(v) => v,
+            None => return vec![invalid("Invalid root type, expecting array".to_string())]
+        };
+
+        let mut ret = vec![];
+        for (idx, entity) in entities.iter().enumerate() {
+            let entity = match entity.as_object() {
+                Some(m) => m,
+                None => {
+                    ret.push(invalid("Invalid entity data type, expecting object".to_string()).with_entity(idx));
+                    continue
+                }
+            };
+            let entity_name = proto_entity_name(entity);
+
+            let is_ref = entity.contains_key(PROTO_REF_KEY);
+            let components = if is_ref {
+                match entity.get(PROTO_OVERRIDES_KEY) {
+                    Some(Value::Object(overrides)) => Some(overrides),
+                    _ => None
+                }
+            } else {
+                Some(entity)
+            };
+
+            for (name, data) in components.into_iter().flatten() {
+                if !is_ref && name.as_str() == PROTO_VERSIONS_KEY {
+                    continue
+                }
+                if !self.components.contains_key(name) {
+                    ret.push(invalid(format!("Unknown component {}", name)).with_entity(idx).with_entity_name(entity_name));
+                    continue
+                }
+                if is_ref {
+                    continue
+                }
+
+                if let Some(Err(errors)) = compiled.get(name.as_str()).map(|x| x.validate(data)) {
+                    for e in errors {
+                        let msg = if e.instance_path.to_string().is_empty() {
+                            e.to_string()
+                        } else {
+                            format!("{} (at {})", e, e.instance_path)
+                        };
+                        ret.push(invalid(msg).with_entity(idx).with_entity_name(entity_name).with_component(name));
+                    }
+                }
+            }
+        }
+        ret
+    }
+
+    /// Validates every proto file under `dir`, after migrating it to the current versions.
+    /// Returns all problems found, or an error if a file can't be read or parsed.
+    pub fn validate_dir(&self, dir: &std::path::Path, migrations: &ProtoMigrations) -> Result<Vec<ProtoError>, ProtoError> {
+        let compiled = self.compile();
+        let (files, mut ret) = read_proto_files(dir)?;
+        for (path, _, mut proto) in files {
+            let mut migrated = true;
+            for (idx, entity) in proto.as_array_mut().unwrap().iter_mut().enumerate() {
+                if let Value::Object(m) = entity {
+                    let entity_name = proto_entity_name(m).map(|x| x.to_string());
+                    if let Err(e) = migrations.upgrade_entity(m) {
+                        ret.push(ProtoError::new(&path, e).with_entity(idx).with_entity_name(entity_name.as_deref()));
+                        migrated = false;
+                    }
+                }
+            }
+            if migrated {
+                ret.extend(self.validate_with(&compiled, &path, &proto));
+            }
+        }
+        Ok(ret)
+    }
+
+}
+
+/// `ComponentS11n` of a `ProtoComponent`.
+pub struct ProtoComponentS11n<T>(PhantomData<T>);
+
+impl<T> ProtoComponentS11n<T> {
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T> Default for ProtoComponentS11n<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for ProtoComponentS11n<T> {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T: ProtoComponent> ComponentS11n<'a> for ProtoComponentS11n<T> {
+    type SystemData = WriteExpect<'a, ResManager>;
+    type StoreSystemData = ReadExpect<'a, ResManager>;
+    type Output = T;
+    type Loaded = T;
+
+    fn load_async(&mut self, ctx: ComponentLoadArgs, res_mgr: &mut Self::SystemData) -> Result<ComponentLoadFuture<Self::Loaded>, ProtoErrorKind> {
+        let ret = T::load(ctx.data, &mut ProtoLoadCtx {
+            entities: ctx.all_entity_vec,
+            res_mgr: &mut *res_mgr
+        })?;
+        Ok(Box::pin(async move { Ok(ret) }))
+    }
 
-pub trait ComponentS11n<'a> {
-    type SystemData: SystemData<'a>;
-    type StoreSystemData: SystemData<'a>;
-    type Output: Component + Send + Sync;
+    fn integrate(&mut self, loaded: Self::Loaded, _: &mut Self::SystemData) -> Result<Self::Output, ProtoErrorKind> {
+        Ok(loaded)
+    }
 
-    fn load_async(&mut self, ctx: ComponentLoadArgs, system_data: &mut Self::SystemData) -> Pin<Box<dyn Future<Output = Self::Output> + Send + Sync>>;
+    fn store(&mut self, ctx: ComponentStoreArgs<Self::Output>, res_mgr: &mut Self::StoreSystemData) -> Option<Value> {
+        let result = ctx.component.store(&ProtoStoreCtx {
+            entities: ctx.all_entity_vec,
+            res_mgr: &*res_mgr
+        });
+        result.map_err(|e| warn!("Can't store {}, omitting it: {}", T::PROTO_NAME, e)).ok()
+    }
 
-    fn store(&mut self, ctx: ComponentStoreArgs<Self::Output>, system_data: &mut Self::StoreSystemData) -> Value;
+    fn type_name(&self) -> &'static str {
+        T::PROTO_NAME
+    }
 
-    /// Get type name literal used in json representation.
-    /// We can use std::any::type_name, but that has no stability guarantee.
-    fn type_name(&self) -> &'static str;
-}
+    fn version(&self) -> u32 {
+        T::PROTO_VERSION
+    }
 
-#[derive(Clone)]
-pub struct ComponentS11nDefault<T>
-    where T: Send + Sync + Serialize + DeserializeOwned {
-    name: &'static str,
-    marker: PhantomData<T>
+    fn schema(&self) -> Option<Value> {
+        T::schema()
+    }
 }
 
-impl<T> ComponentS11nDefault<T>
-    where T: Send + Sync + Serialize + DeserializeOwned {
-    pub fn new(name: &'static str) -> Self {
-        Self {
-            name,
-            marker: PhantomData
+/// Serde adapter for `ResourceRef<T>` fields where `T: LoadableAsset`, storing the asset path.
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct Dialogue {
+///     #[serde(with = "mu::proto::asset_ref")]
+///     script: ResourceRef<String>
+/// }
+/// ```
+///
+/// (De)serialization must happen inside `with_res_mgr` or `with_res_mgr_mut`, which
+/// `ComponentS11nDefault::with_assets` already does. Deserializing inside `with_res_mgr_mut`
+/// loads the asset if it isn't loaded yet, inside `with_res_mgr` it must already be loaded.
+pub mod asset_ref {
+    use std::cell::Cell;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error as DeError;
+    use serde::ser::Error as SerError;
+
+    use crate::asset::LoadableAsset;
+    use crate::resource::{ResManager, ResourceRef};
+
+    #[derive(Copy, Clone)]
+    enum ResMgrAccess {
+        None,
+        Read(*const ResManager),
+        Write(*mut ResManager)
+    }
+
+    thread_local! {
+    static CURRENT_RES_MGR: Cell<ResMgrAccess> = const { Cell::new(ResMgrAccess::None) };
+    }
+
+    /// Restores the previous context, also when unwinding.
+    struct ScopeGuard(ResMgrAccess);
+
+    impl Drop for ScopeGuard {
+        fn drop(&mut self) {
+            CURRENT_RES_MGR.with(|x| x.set(self.0));
         }
     }
-}
 
-impl<'a, T> ComponentS11n<'a> for ComponentS11nDefault<T>
-    where T: Component + Send + Sync + Serialize + DeserializeOwned
-{
-    type SystemData = ();
-    type StoreSystemData = ();
-    type Output = T;
+    fn with_access<R>(access: ResMgrAccess, f: impl FnOnce() -> R) -> R {
+        let _guard = ScopeGuard(CURRENT_RES_MGR.with(|x| x.replace(access)));
+        f()
+    }
 
-    fn load_async(&mut self, ctx: ComponentLoadArgs, _: &mut Self::SystemData)
-        -> Pin<Box<dyn Future<Output=Self::Output> + Send + Sync>> {
-        let v = ctx.data;
-        Box::pin(async move {
-            let ret: T = serde_json::from_value(v).unwrap();
-            ret
-        })
+    /// Serialization only, deserializing can only look up already loaded assets.
+    pub fn with_res_mgr<R>(res_mgr: &ResManager, f: impl FnOnce() -> R) -> R {
+        with_access(ResMgrAccess::Read(res_mgr), f)
     }
 
-    fn store(&mut self, ctx: ComponentStoreArgs<Self::Output>, _system_data: &mut Self::StoreSystemData)
-        -> Value {
-        serde_json::to_value(ctx.component).expect("Serialize failed")
+    pub fn with_res_mgr_mut<R>(res_mgr: &mut ResManager, f: impl FnOnce() -> R) -> R {
+        with_access(ResMgrAccess::Write(res_mgr), f)
     }
 
-    fn type_name(&self) -> &'static str {
-        self.name
+    pub fn serialize<S, T>(res_ref: &ResourceRef<T>, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer, T: 'static + Send + Sync {
+        let access = CURRENT_RES_MGR.with(|x| x.get());
+        // Safety: the pointer is only set while the borrow in `with_access` is alive
+        let res_mgr: &ResManager = match access {
+            ResMgrAccess::Read(ptr) => unsafe { &*ptr },
+            ResMgrAccess::Write(ptr) => unsafe { &*ptr },
+            ResMgrAccess::None => return Err(S::Error::custom("ResourceRef serialized outside of with_res_mgr"))
+        };
+
+        match res_mgr.path_of(res_ref) {
+            Some(path) => serializer.serialize_str(path),
+            None => Err(S::Error::custom(format!("{:?} isn't loaded from path", res_ref)))
+        }
     }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<ResourceRef<T>, D::Error>
+        where D: Deserializer<'de>, T: 'static + LoadableAsset + Send + Sync {
+        let path = String::deserialize(deserializer)?;
+        let access = CURRENT_RES_MGR.with(|x| x.get());
+        match access {
+            ResMgrAccess::Write(ptr) => {
+                let res_mgr = unsafe { &mut *ptr };
+                res_mgr.load_asset(&path)
+                    .map_err(|e| D::Error::custom(format!("Failed to load {}: {}", path, e)))
+            },
+            ResMgrAccess::Read(ptr) => {
+                let res_mgr = unsafe { &*ptr };
+                res_mgr.get_by_path(&path)
+                    .ok_or_else(|| D::Error::custom(format!("{} isn't loaded", path)))
+            },
+            ResMgrAccess::None => Err(D::Error::custom("ResourceRef deserialized outside of with_res_mgr"))
+        }
+    }
+
 }
 
+/// Slot filled by a `load_async` future once it finishes. The `ComponentS11n::Loaded` value is
+/// type erased, since it's named through the `'a` of the system data.
+type StagingSlot = Arc<Mutex<Poll<Result<Box<dyn Any + Send>, ProtoErrorKind>>>>;
+
 pub struct ComponentStagingData<T> where T: Component {
-    staging_components: HashMap<(u32, usize), Arc<Mutex<Poll<T>>>>,
-    #[allow(dead_code)]
-    thread_pool: ThreadPool
+    staging_components: HashMap<(u32, usize), StagingSlot>,
+    marker: PhantomData<T>
 }
 
 impl<T: Component> Default for ComponentStagingData<T> {
     fn default() -> Self {
         Self {
             staging_components: HashMap::new(),
-            thread_pool: ThreadPool::new().unwrap()
+            marker: PhantomData
         }
     }
 }
 
+/// The thread pool `ComponentS11n::load_async` futures run on.
+pub struct ProtoThreadPool(pub ThreadPool);
+
+fn spawn_load<L: Send + 'static>(thread_pool: &ThreadPool, fut: ComponentLoadFuture<L>) -> StagingSlot {
+    let slot: StagingSlot = Arc::new(Mutex::new(Poll::Pending));
+    let slot_clone = slot.clone();
+    thread_pool.spawn_ok(async move {
+        let result = fut.await.map(|x| Box::new(x) as Box<dyn Any + Send>);
+        *slot_clone.lock().unwrap() = Poll::Ready(result);
+    });
+    slot
+}
+
+/// Query of all entities with any serializable component.
+///
+/// Set `requested` in a system before `DEP_PROTO_STORE`. In the next frame `ready` is set and
+/// `result` holds the entities, until `ProtoStoreSystem` runs. Several systems may request it.
+#[derive(Default)]
+pub struct SerializableEntityQuery {
+    pub requested: bool,
+    pub ready: bool,
+    pub result: BitSet
+}
+
+/// Components of a loaded proto that no registered `ComponentS11n` handles, e.g. of a module
+/// that isn't loaded. They are written back unchanged when the entity is stored.
+#[derive(Component, Clone, Debug, Default)]
+#[storage(HashMapStorage)]
+pub struct UnknownComponents {
+    pub components: serde_json::Map<String, Value>,
+    /// Their versions from `$versions`, only those other than 1.
+    pub versions: serde_json::Map<String, Value>
+}
+
+/// Index of the entity in the proto it was loaded from, counting the entities of nested protos
+/// where they are referenced. Saved scenes keep this order, see `scene::scene_order`.
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LoadIndex(pub usize);
+
 pub(super) struct ProtoModule;
 
 impl Module for ProtoModule {
@@ -169,18 +1612,91 @@ impl Module for ProtoModule {
         if ctx.init_data.world.try_fetch::<ProtoStoreGlobalData>().is_none() {
             ctx.init_data.world.insert(internal::ProtoStoreGlobalData::default());
         }
+        if ctx.init_data.world.try_fetch::<ProtoMigrations>().is_none() {
+            ctx.init_data.world.insert(ProtoMigrations::builtin());
+        }
+        if ctx.init_data.world.try_fetch::<ProtoSchemas>().is_none() {
+            ctx.init_data.world.insert(ProtoSchemas::builtin());
+        }
         ctx.init_data.world.insert(ProtoLoadRequests::new());
         ctx.init_data.world.insert(ProtoLoadContexts::new());
+        ctx.init_data.world.insert(ProtoThreadPool(ThreadPool::new().unwrap()));
+        ctx.init_data.world.insert(LoadingBatches::new());
+        ctx.init_data.world.insert(EventChannel::<LoadingBatchFinished>::new());
+        // Keep recently spawned protos parsed even when nothing references them
+        ctx.init_data.res_mgr.set_retention_policy::<ProtoTemplate>(RetentionPolicy::lru_count(32));
 
         ctx.dispatch(InsertInfo::new(DEP_PROTO_LOAD),
                      |_, i| i.insert(internal::ProtoLoadSystem::new()));
         ctx.dispatch(InsertInfo::new(DEP_PROTO_STORE),
                      |_, i| i.insert(internal::ProtoStoreSystem));
+        ctx.dispatch(InsertInfo::new("").after(&[DEP_PROTO_LOAD]),
+                     |_, i| i.insert(internal::LoadingBatchSystem));
+    }
+}
+
+/// Anything `ComponentS11n`s are registered with: the `InitContext`, and `ProtoMigrations` and
+/// `ProtoSchemas` for tools working without a `Runtime`.
+///
+/// A game registering its components through this in one function can use it for all of them,
+/// e.g. to validate its protos with its own components:
+///
+/// ```ignore
+/// let mut schemas = ProtoSchemas::builtin();
+/// let mut migrations = ProtoMigrations::builtin();
+/// my_game::register_components(&mut schemas);
+/// my_game::register_components(&mut migrations);
+/// let errors = schemas.validate_dir(Path::new("assets"), &migrations)?;
+/// ```
+pub trait ComponentS11nRegistry {
+    fn register_s11n<T: 'static + for<'a> ComponentS11n<'a> + Send + Clone>(&mut self, s11n: T);
+}
+
+impl ComponentS11nRegistry for ProtoMigrations {
+    fn register_s11n<T: 'static + for<'a> ComponentS11n<'a> + Send + Clone>(&mut self, s11n: T) {
+        self.register(&s11n);
+    }
+}
+
+impl ComponentS11nRegistry for ProtoSchemas {
+    fn register_s11n<T: 'static + for<'a> ComponentS11n<'a> + Send + Clone>(&mut self, s11n: T) {
+        self.register(&s11n);
     }
 }
 
+impl ComponentS11nRegistry for super::InitContext {
+    fn register_s11n<T: 'static + for<'a> ComponentS11n<'a> + Send + Clone>(&mut self, s11n: T) {
+        self.add_component_s11n(s11n);
+    }
+}
+
+/// Components every `Runtime` registers.
+pub fn register_default_components(registry: &mut impl ComponentS11nRegistry) {
+    registry.register_s11n(ComponentS11nDefault::<crate::ecs::Transform>::new("Transform").with_schema());
+    registry.register_s11n(ComponentS11nDefault::<crate::ecs::Transform2D>::new("Transform2D").with_schema());
+    registry.register_s11n(crate::ecs::HasParentS11n);
+    registry.register_s11n(ComponentS11nDefault::<crate::ecs::SiblingOrder>::new("SiblingOrder").with_schema());
+    registry.register_s11n(ComponentS11nDefault::<crate::name::Name>::new("Name").with_schema());
+    registry.register_s11n(ComponentS11nDefault::<crate::name::Tags>::new("Tags").with_schema());
+}
+
+/// The default components and those of the engine's optional modules, which `ProtoMigrations::builtin`
+/// and `ProtoSchemas::builtin` know.
+pub fn register_engine_components(registry: &mut impl ComponentS11nRegistry) {
+    register_default_components(registry);
+    registry.register_s11n(crate::client::sprite::SpriteRendererS11n);
+}
+
 pub trait InitContextProtoExt {
     fn add_component_s11n<T: 'static + for<'a> ComponentS11n<'a> + Send + Clone>(&mut self, s11n: T);
+
+    fn add_proto_component<T: ProtoComponent>(&mut self) {
+        self.add_component_s11n(ProtoComponentS11n::<T>::new());
+    }
+
+    /// Registers a migration of `component` data from `from_version` to `from_version + 1`.
+    fn add_proto_migration<F>(&mut self, component: &str, from_version: u32, migration: F)
+        where F: Fn(Value) -> Result<Value, ProtoErrorKind> + Send + Sync + 'static;
 }
 
 impl InitContextProtoExt for super::InitContext {
@@ -191,15 +1707,34 @@ impl InitContextProtoExt for super::InitContext {
 
         self.init_data.world.write_resource::<ProtoStoreGlobalData>()
             .all_component_names.push(s11n.type_name());
+        migrations_mut(&mut self.init_data.world).register(&s11n);
+        if self.init_data.world.try_fetch::<ProtoSchemas>().is_none() {
+            self.init_data.world.insert(ProtoSchemas::builtin());
+        }
+        self.init_data.world.write_resource::<ProtoSchemas>().register(&s11n);
         let cloned_s11n = s11n.clone();
         self.dispatch(InsertInfo::default().after(&[DEP_PROTO_LOAD]),
             |_, i| i.insert(ComponentLoadSystem(cloned_s11n)));
         self.dispatch(InsertInfo::default().after(&[DEP_PROTO_STORE]),
                       |_, i| i.insert(ComponentStoreSystem(s11n)));
     }
+
+    fn add_proto_migration<F>(&mut self, component: &str, from_version: u32, migration: F)
+        where F: Fn(Value) -> Result<Value, ProtoErrorKind> + Send + Sync + 'static {
+        migrations_mut(&mut self.init_data.world).add(component, from_version, migration);
+    }
+}
+
+fn migrations_mut(world: &mut World) -> specs::shred::FetchMut<'_, ProtoMigrations> {
+    if world.try_fetch::<ProtoMigrations>().is_none() {
+        world.insert(ProtoMigrations::builtin());
+    }
+    world.write_resource::<ProtoMigrations>()
 }
 
 pub(super) mod internal {
+    use specs::hibitset::BitSetLike;
+
     use super::*;
 
     pub struct ProtoStoreGlobalData {
@@ -225,6 +1760,7 @@ pub(super) mod internal {
         Init(Value),
         Processing,
         Finished,
+        Failed(ProtoErrorKind),
         Integrate,
         Finalize
     }
@@ -257,15 +1793,49 @@ pub(super) mod internal {
     }
 
     pub struct LoadingEntity {
+        /// See `ProtoError::entity_name`.
+        pub name: Option<String>,
         pub components: HashMap<String, ComponentLoadState>,
+        /// (scope, index in scope) each component resolves entity indices with, see `ProtoLoadContext::scopes`.
+        pub component_scopes: HashMap<String, (usize, usize)>
     }
 
     pub struct ProtoLoadContext {
         pub idx: u32, // An unique id to distinguish between load requests
         pub loading_entities: Vec<LoadingEntity>,
         pub entities: Vec<Entity>,
+        /// Entities of every proto file instance in this load, indexed the same as the entries
+        /// of that file. A `$proto` entry maps to the root of the nested instance.
+        pub scopes: Vec<Arc<[Entity]>>,
+        pub scope_paths: Vec<String>,
+        /// See `ProtoLoadRequest::instance`.
+        pub instance: bool,
         pub state: ProtoLoadState,
-        pub result: ProtoLoadResult
+        pub result: ProtoLoadResult,
+        pub progress: ProtoLoadProgress
+    }
+
+    impl ProtoLoadContext {
+
+        fn take_error(&mut self) -> Option<ProtoError> {
+            for ent in &mut self.loading_entities {
+                for (name, state) in &mut ent.components {
+                    if let ComponentLoadState::Failed(_) = state {
+                        let (scope, scope_idx) = ent.component_scopes[name];
+                        let kind = match std::mem::replace(state, ComponentLoadState::Finalize) {
+                            ComponentLoadState::Failed(kind) => kind,
+                            _ => unreachable!()
+                        };
+                        return Some(ProtoError::new(&self.scope_paths[scope], kind)
+                            .with_entity(scope_idx)
+                            .with_entity_name(ent.name.as_deref())
+                            .with_component(name))
+                    }
+                }
+            }
+            None
+        }
+
     }
 
     pub struct ProtoLoadContexts {
@@ -289,76 +1859,82 @@ pub(super) mod internal {
         type SystemData = (
             WriteExpect<'a, ProtoLoadContexts>,
             Write<'a, ComponentStagingData<T::Output>>,
+            ReadExpect<'a, ProtoThreadPool>,
             WriteStorage<'a, T::Output>,
             T::SystemData);
 
-        fn run(&mut self, (mut proto_loads, mut staging_data, mut cmpt_write, mut data): Self::SystemData) {
+        fn run(&mut self, (mut proto_loads, mut staging_data, thread_pool, mut cmpt_write, mut data): Self::SystemData) {
+            // Drop results of failed requests
+            if !staging_data.staging_components.is_empty() {
+                let proto_loads = &proto_loads;
+                staging_data.staging_components.retain(|(ctx_idx, _), _| proto_loads.v.iter().any(|x| x.idx == *ctx_idx));
+            }
+
             for entry in &mut proto_loads.v {
                 for (idx, ent) in entry.loading_entities.iter_mut().enumerate() {
                     let key = (entry.idx, idx);
                     if let Some(state) = ent.components.get_mut(self.0.type_name()) {
                         let next_state = match &state {
-                            ComponentLoadState::Init(v) => { // Init时，目前直接调用序列化代码
-                                let arc = Arc::new(Mutex::new(Poll::Pending));
-                                let arc_clone = arc.clone();
-
+                            ComponentLoadState::Init(v) => {
                                 // TODO: Useless and expensive clone
                                 let temp_value = v.clone();
+                                let (scope, scope_idx) = ent.component_scopes[self.0.type_name()];
 
                                 let fut = self.0.load_async(ComponentLoadArgs {
                                     data: temp_value,
-                                    entity_idx: idx,
-                                    all_entity_vec: &entry.entities
+                                    entity_idx: scope_idx,
+                                    all_entity_vec: &entry.scopes[scope],
+                                    progress: &entry.progress
                                 }, &mut data);
-                                // https://github.com/rust-lang/rust/issues/71723
-                                // 下面是预期的真正的async load代码，但是遇到了个奇怪的编译器报错，
-                                //     | ...                   staging_data.thread_pool.spawn_ok(async move {}
-                                //     |                                                ^^^^^^^^ one type is more general than the other
-                                //     |
-                                //     = note: expected type `proto::ComponentS11n<'_>`
-                                //                found type `proto::ComponentS11n<'a>`
-                                // 暂时不知如何解决，故先做成blocking了
-                                // staging_data.thread_pool.spawn_ok(async move {
-                                //     let loaded_data = fut.await;
-                                //     *arc_clone.lock().unwrap() = Poll::Ready(loaded_data);
-                                // });
-
-                                {
-                                    let loaded_data = block_on(fut);
-                                    *arc_clone.lock().unwrap() = Poll::Ready(loaded_data);
-                                }
-
-                                staging_data.staging_components.insert((entry.idx, idx), arc);
+                                let fut = match fut {
+                                    Ok(fut) => fut,
+                                    Err(e) => {
+                                        *state = ComponentLoadState::Failed(e);
+                                        continue
+                                    }
+                                };
+                                // 在泛型函数中spawn，避免async块的类型带上'a (rust-lang/rust#71723)
+                                let slot = spawn_load(&thread_pool.0, fut);
+                                staging_data.staging_components.insert(key, slot);
                                 Some(ComponentLoadState::Processing)
                             },
                             ComponentLoadState::Processing => {
-                                let can_remove = {
-                                    let ref x = staging_data.staging_components[&key];
-                                    x.lock().unwrap().is_ready()
-                                };
-
-                                if can_remove {
-                                    Some(ComponentLoadState::Finished)
-                                } else {
-                                    None
+                                let mut x = staging_data.staging_components[&key].lock().unwrap();
+                                match &mut *x {
+                                    Poll::Ready(Err(_)) => {
+                                        let e = match std::mem::replace(&mut *x, Poll::Pending) {
+                                            Poll::Ready(Err(e)) => e,
+                                            _ => unreachable!()
+                                        };
+                                        drop(x);
+                                        staging_data.staging_components.remove(&key);
+                                        Some(ComponentLoadState::Failed(e))
+                                    },
+                                    Poll::Ready(Ok(_)) => {
+                                        entry.progress.complete_component();
+                                        Some(ComponentLoadState::Finished)
+                                    },
+                                    Poll::Pending => None
                                 }
                             },
                             ComponentLoadState::Integrate => {
                                 let e = entry.entities[idx];
                                 let result_arc = staging_data.staging_components.remove(&key).unwrap();
-                                let result = Arc::try_unwrap(result_arc)
-                                    .unwrap_or_else(|_| panic!())
-                                    .into_inner()
-                                    .unwrap();
+                                // The pool thread may still hold its clone of the slot, so take the value out
+                                let result = std::mem::replace(&mut *result_arc.lock().unwrap(), Poll::Pending);
 
-                                match result {
-                                    Poll::Ready(cmpt) => {
-                                        cmpt_write.insert(e, cmpt).unwrap();
-                                    }
+                                let loaded = match result {
+                                    Poll::Ready(Ok(loaded)) => loaded.downcast::<T::Loaded>().unwrap(),
                                     _ => unreachable!()
-                                }
+                                };
 
-                                Some(ComponentLoadState::Finalize)
+                                match self.0.integrate(*loaded, &mut data) {
+                                    Ok(cmpt) => {
+                                        cmpt_write.insert(e, cmpt).unwrap();
+                                        Some(ComponentLoadState::Finalize)
+                                    },
+                                    Err(err) => Some(ComponentLoadState::Failed(err))
+                                }
                             },
                             _ => None,
                         };
@@ -387,6 +1963,8 @@ pub(super) mod internal {
 
     pub struct ProtoStoreContext {
         pub target_path: String,
+        pub to_value: bool,
+        pub result: ProtoStoreResult,
         pub entities: Vec<Entity>,
         pub results: Vec<StoringEntity>,
         pub state: ProtoStoreState
@@ -400,11 +1978,18 @@ pub(super) mod internal {
         where T: ComponentS11n<'a>, <T as ComponentS11n<'a>>::Output: Component {
         type SystemData = (
             WriteExpect<'a, ProtoStoreContexts>,
+            Write<'a, SerializableEntityQuery>,
             ReadStorage<'a, T::Output>,
             T::StoreSystemData
         );
 
-        fn run(&mut self, (mut proto_stores, cmpt_read, mut data): Self::SystemData) {
+        fn run(&mut self, (mut proto_stores, mut query, cmpt_read, mut data): Self::SystemData) {
+            if query.ready {
+                for id in cmpt_read.mask().iter() {
+                    query.result.add(id);
+                }
+            }
+
             for ctx in &mut *proto_stores {
                 if ctx.state != ProtoStoreState::Processing {
                     continue
@@ -417,7 +2002,7 @@ pub(super) mod internal {
 
                     let cmpt = cmpt_read.get(ctx.entities[i]);
                     *ctx.results[i].components.get_mut(self.0.type_name()).unwrap() = ComponentStoreState::Stored(
-                        cmpt.map(|val| {
+                        cmpt.and_then(|val| {
                             self.0.store(ComponentStoreArgs {
                                 component: val,
                                 entity_idx: i,
@@ -430,6 +2015,143 @@ pub(super) mod internal {
         }
     }
 
+    pub const PROTO_REF_KEY: &str = "$proto";
+    pub const PROTO_OVERRIDES_KEY: &str = "overrides";
+
+    pub struct ExpandedEntity {
+        pub components: serde_json::Map<String, Value>,
+        pub component_scopes: HashMap<String, (usize, usize)>,
+        /// `$versions` entries of components without registered version.
+        pub unknown_versions: serde_json::Map<String, Value>
+    }
+
+    /// A proto with all `$proto` entries recursively replaced by the entities of the referenced file.
+    #[derive(Default)]
+    pub struct ExpandedProto {
+        pub entities: Vec<ExpandedEntity>,
+        /// For each expanded file instance, index into `entities` of each of its entries.
+        pub scopes: Vec<Vec<usize>>,
+        pub scope_paths: Vec<String>
+    }
+
+    impl ExpandedProto {
+
+        /// Expands the proto at `path`, returning index of the scope of it.
+        ///
+        /// Entries of the form `{"$proto": "path.json", "overrides": {...}}` are replaced by the
+        /// entities of that file, the first of which (the root) receives the overrides.
+        /// `stack` holds files currently being expanded, used to detect cycles.
+        pub fn expand_file(&mut self, path: &str, stack: &mut Vec<String>, migrations: &ProtoMigrations,
+                           read_file: &mut dyn FnMut(&str) -> Result<Value, ProtoErrorKind>) -> Result<usize, ProtoError> {
+            if stack.iter().any(|x| x == path) {
+                let mut chain = stack.clone();
+                chain.push(path.to_string());
+                return Err(ProtoError::new(path, ProtoErrorKind::CyclicReference(chain)))
+            }
+
+            let invalid = |msg: &str| ProtoError::new(path, ProtoErrorKind::Invalid(msg.to_string()));
+            let entries = match read_file(path).map_err(|e| ProtoError::new(path, e))? {
+                Value::Array(v) => v,
+                _ => return Err(invalid("Invalid root type, expecting array"))
+            };
+
+            stack.push(path.to_string());
+            let scope = self.scopes.len();
+            self.scopes.push(vec![]);
+            self.scope_paths.push(path.to_string());
+
+            let mut scope_entities = Vec::with_capacity(entries.len());
+            for (entry_idx, entry) in entries.into_iter().enumerate() {
+                let mut m = match entry {
+                    Value::Object(m) => m,
+                    _ => return Err(invalid("Invalid entity data type, expecting object").with_entity(entry_idx))
+                };
+                migrations.upgrade_entity(&mut m)
+                    .map_err(|e| ProtoError::new(path, e).with_entity(entry_idx).with_entity_name(proto_entity_name(&m)))?;
+                // Versions of registered components are current now, others are kept for storing back
+                let unknown_versions = match m.remove(PROTO_VERSIONS_KEY) {
+                    Some(Value::Object(versions)) => versions.into_iter()
+                        .filter(|(k, _)| migrations.version(k).is_none())
+                        .collect(),
+                    _ => serde_json::Map::new()
+                };
+
+                let ix = match m.remove(PROTO_REF_KEY) {
+                    Some(Value::String(child_path)) => {
+                        let child_scope = self.expand_file(&child_path, stack, migrations, read_file)?;
+                        let root = *self.scopes[child_scope].first()
+                            .ok_or_else(|| invalid(&format!("Referenced proto {} has no entity", child_path))
+                                .with_entity(entry_idx))?;
+
+                        self.entities[root].unknown_versions.extend(unknown_versions);
+                        if let Some(overrides) = m.remove(PROTO_OVERRIDES_KEY) {
+                            match overrides {
+                                Value::Object(overrides) =>
+                                    self.apply_overrides(root, overrides, (scope, entry_idx)),
+                                _ => return Err(invalid("Invalid overrides, expecting object").with_entity(entry_idx))
+                            }
+                        }
+                        if let Some(k) = m.keys().next() {
+                            return Err(invalid(&format!("Unexpected key {} in proto reference", k)).with_entity(entry_idx))
+                        }
+
+                        root
+                    },
+                    Some(_) => return Err(invalid(&format!("Invalid {}, expecting path string", PROTO_REF_KEY))
+                        .with_entity(entry_idx)),
+                    None => {
+                        self.entities.push(ExpandedEntity {
+                            component_scopes: m.keys().map(|k| (k.clone(), (scope, entry_idx))).collect(),
+                            components: m,
+                            unknown_versions
+                        });
+                        self.entities.len() - 1
+                    }
+                };
+                scope_entities.push(ix);
+            }
+
+            self.scopes[scope] = scope_entities;
+            stack.pop();
+            Ok(scope)
+        }
+
+        /// A `null` removes the component, an object is merged field by field into an existing
+        /// component, anything else replaces it. Overridden components resolve entity indices
+        /// in the referencing file.
+        fn apply_overrides(&mut self, ix: usize, overrides: serde_json::Map<String, Value>, scope: (usize, usize)) {
+            let entity = &mut self.entities[ix];
+            for (name, value) in overrides {
+                if value.is_null() {
+                    entity.components.remove(&name);
+                    entity.component_scopes.remove(&name);
+                    continue
+                }
+
+                match entity.components.get_mut(&name) {
+                    Some(existing) => merge_value(existing, value),
+                    None => { entity.components.insert(name.clone(), value); }
+                }
+                entity.component_scopes.insert(name, scope);
+            }
+        }
+
+    }
+
+    fn merge_value(target: &mut Value, value: Value) {
+        match (target, value) {
+            (Value::Object(target), Value::Object(fields)) => {
+                for (k, v) in fields {
+                    match target.get_mut(&k) {
+                        Some(existing) => merge_value(existing, v),
+                        None => { target.insert(k, v); }
+                    }
+                }
+            },
+            (target, value) => *target = value
+        }
+    }
+
     pub struct ProtoLoadSystem {
         counter: u32
     }
@@ -443,49 +2165,105 @@ pub(super) mod internal {
     }
 
     impl<'a> System<'a> for ProtoLoadSystem {
-        type SystemData = (WriteExpect<'a, ProtoLoadRequests>, WriteExpect<'a, ProtoLoadContexts>, Entities<'a>);
-
-        fn run(&mut self, (mut requests, mut proto_loads, entities): Self::SystemData) {
+        type SystemData = (
+            WriteExpect<'a, ProtoLoadRequests>,
+            WriteExpect<'a, ProtoLoadContexts>,
+            ReadExpect<'a, ProtoMigrations>,
+            ReadExpect<'a, ProtoStoreGlobalData>,
+            WriteExpect<'a, ResManager>,
+            WriteStorage<'a, PrefabInstance>,
+            WriteStorage<'a, UnknownComponents>,
+            WriteStorage<'a, LoadIndex>,
+            Entities<'a>);
+
+        fn run(&mut self, (mut requests, mut proto_loads, migrations, global_data, mut res_mgr,
+            mut instances, mut unknowns, mut load_indices, entities): Self::SystemData) {
             requests.drain(..)
-                .for_each(|req| {
-                    let s: String = asset::load_asset(&req.path).unwrap();
-                    let value: Value = serde_json::from_str(&s).unwrap();
-
-                    let loading_entities = match value {
-                        Value::Array(v) => {
-                            v.into_iter()
-                                .map(|entity_value| {
-                                    match entity_value {
-                                        Value::Object(m) => {
-                                            let mut components = HashMap::new();
-                                            for (k, v) in m {
-                                                components.insert(k, ComponentLoadState::Init(v));
-                                            }
-                                            LoadingEntity {
-                                                components,
-                                            }
-                                        }
-                                        _ => panic!("Invalid entity data type, expecting object")
-                                    }
-                                })
-                                .collect::<Vec<_>>()
+                .for_each(|mut req| {
+                    let mut expanded = ExpandedProto::default();
+                    let mut data = req.data.take();
+                    let mut read_file = |path: &str| match data.take() {
+                        // The request's own data is read first, nested protos come from templates
+                        Some(v) => Ok(v),
+                        None => {
+                            let template = ProtoTemplate::load(&mut res_mgr, path)?;
+                            Ok(res_mgr.get(&template).data.clone())
                         }
-                        _ => panic!("Invalid root type")
                     };
-                    let entity_count = loading_entities.len();
+                    if let Err(e) = expanded.expand_file(&req.path, &mut vec![], &migrations, &mut read_file) {
+                        error!("Failed to load proto {}", e);
+                        *req.result.lock().unwrap() = Poll::Ready(Err(e));
+                        return
+                    }
+
+                    let all_entities: Vec<Entity> = (0..expanded.entities.len())
+                        .map(|ix| {
+                            let entity = entities.create();
+                            load_indices.insert(entity, LoadIndex(ix)).unwrap();
+                            entity
+                        })
+                        .collect();
+                    let scope_paths = &expanded.scope_paths;
+                    let loading_entities = expanded.entities.into_iter()
+                        .zip(&all_entities)
+                        .map(|(x, entity)| {
+                            let entity_name = proto_entity_name(&x.components).map(|x| x.to_string());
+                            // No ComponentLoadSystem would ever pick up unknown components
+                            let (components, unknown): (serde_json::Map<_, _>, serde_json::Map<_, _>) = x.components.into_iter()
+                                .partition(|(k, _)| global_data.all_component_names.contains(&k.as_str()));
+                            if !unknown.is_empty() {
+                                for name in unknown.keys() {
+                                    let (scope, scope_idx) = x.component_scopes[name];
+                                    warn!("{}, keeping its data as is", ProtoError::new(&scope_paths[scope],
+                                        ProtoErrorKind::Invalid(format!("Unknown component {}", name))).with_entity(scope_idx)
+                                        .with_entity_name(entity_name.as_deref()));
+                                }
+                                let versions = x.unknown_versions.into_iter()
+                                    .filter(|(k, _)| unknown.contains_key(k))
+                                    .collect();
+                                unknowns.insert(*entity, UnknownComponents { components: unknown, versions }).unwrap();
+                            }
+
+                            LoadingEntity {
+                                name: entity_name,
+                                components: components.into_iter()
+                                    .map(|(k, v)| (k, ComponentLoadState::Init(v)))
+                                    .collect(),
+                                component_scopes: x.component_scopes
+                            }
+                        })
+                        .collect::<Vec<_>>();
+                    let scopes = expanded.scopes.iter()
+                        .map(|scope| scope.iter().map(|ix| all_entities[*ix]).collect())
+                        .collect();
 
+                    req.progress.add_components(loading_entities.iter().map(|x| x.components.len()).sum());
                     let ctx = ProtoLoadContext {
                         idx: self.counter,
                         loading_entities,
                         result: req.result,
+                        progress: req.progress,
                         state: ProtoLoadState::ComponentLoad,
-                        entities: (0..entity_count).map(|_| entities.create()).collect()
+                        entities: all_entities,
+                        scopes,
+                        scope_paths: expanded.scope_paths,
+                        instance: req.instance
                     };
                     proto_loads.v.push(ctx);
                     self.counter += 1;
                 });
 
             for ctx in &mut proto_loads.v {
+                if let Some(e) = ctx.take_error() {
+                    error!("Failed to load proto {}", e);
+                    for e in ctx.entities.drain(..) {
+                        entities.delete(e).unwrap();
+                    }
+                    *ctx.result.lock().unwrap() = Poll::Ready(Err(e));
+                    ctx.state = ProtoLoadState::Finalize;
+                    continue
+                }
+
                 match ctx.state {
                     ProtoLoadState::ComponentLoad => {
                         // 若所有组件加载完成 则进入Integrate状态
@@ -507,7 +2285,17 @@ pub(super) mod internal {
                         if ctx.loading_entities.iter()
                             .all(|x| x.components.values()
                                 .all(|y| match y { ComponentLoadState::Finalize => true, _ => false }) ){
-                            *ctx.result.lock().unwrap() = Poll::Ready(ctx.entities.drain(..).collect());
+                            let spawned: Vec<Entity> = ctx.entities.drain(..).collect();
+                            if let (true, Some(root)) = (ctx.instance, spawned.first()) {
+                                let instance = PrefabInstance {
+                                    source: ctx.scope_paths[0].clone(),
+                                    entities: spawned.clone()
+                                };
+                                if instances.insert(*root, instance).is_err() {
+                                    warn!("Root of {} was deleted while loading, it isn't a prefab instance", ctx.scope_paths[0]);
+                                }
+                            }
+                            *ctx.result.lock().unwrap() = Poll::Ready(Ok(spawned));
                             ctx.state = ProtoLoadState::Finalize;
                         }
                     }
@@ -519,16 +2307,83 @@ pub(super) mod internal {
         }
     }
 
+    /// Json object of an entity with the stored components and its `UnknownComponents`.
+    pub fn stored_entity(mut components: Vec<(String, Value)>, migrations: &ProtoMigrations,
+                         unknown: Option<&UnknownComponents>) -> Value {
+        let mut versions = migrations.versions_value(components.iter().map(|(k, _)| k.as_str()));
+
+        if let Some(unknown) = unknown {
+            // A registered component of the same name takes precedence
+            let known: Vec<String> = components.iter().map(|(k, _)| k.clone()).collect();
+            components.extend(unknown.components.iter()
+                .filter(|(k, _)| !known.contains(k))
+                .map(|(k, v)| (k.clone(), v.clone())));
+            let unknown_versions: serde_json::Map<_, _> = unknown.versions.iter()
+                .filter(|(k, _)| !known.contains(k))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            if !unknown_versions.is_empty() {
+                versions.get_or_insert_with(|| Value::Object(serde_json::Map::new()))
+                    .as_object_mut().unwrap()
+                    .extend(unknown_versions);
+            }
+        }
+
+        // Sort by name, so the output is stable regardless of map implementation
+        components.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut obj: serde_json::Map<_, _> = components.into_iter().collect();
+        if let Some(versions) = versions {
+            obj.insert(PROTO_VERSIONS_KEY.to_string(), versions);
+        }
+        Value::Object(obj)
+    }
+
+    pub struct LoadingBatchSystem;
+
+    impl<'a> System<'a> for LoadingBatchSystem {
+        type SystemData = (
+            WriteExpect<'a, LoadingBatches>,
+            WriteExpect<'a, EventChannel<LoadingBatchFinished>>);
+
+        fn run(&mut self, (mut batches, mut events): Self::SystemData) {
+            batches.retain_mut(|(name, batch)| match batch.poll_finished() {
+                Some(results) => {
+                    events.single_write(LoadingBatchFinished { name: std::mem::take(name), results });
+                    false
+                },
+                None => true
+            });
+        }
+    }
+
     pub struct ProtoStoreSystem;
 
     impl<'a> System<'a> for ProtoStoreSystem {
-        type SystemData = (Write<'a, ProtoStoreRequests>, Write<'a, ProtoStoreContexts>, ReadExpect<'a, ProtoStoreGlobalData>);
+        type SystemData = (
+            Write<'a, ProtoStoreRequests>,
+            Write<'a, ProtoStoreContexts>,
+            ReadExpect<'a, ProtoStoreGlobalData>,
+            ReadExpect<'a, ProtoMigrations>,
+            Write<'a, SerializableEntityQuery>,
+            ReadStorage<'a, UnknownComponents>);
+
+        fn run(&mut self, (mut requests, mut ctxs, global_data, migrations, mut query, unknowns): Self::SystemData) {
+            // Result of last frame is consumed, ComponentStoreSystems fill it anew if requested
+            query.result.clear();
+            query.ready = std::mem::replace(&mut query.requested, false);
+            // Entities with only unknown components are serializable too
+            if query.ready {
+                for id in unknowns.mask().iter() {
+                    query.result.add(id);
+                }
+            }
 
-        fn run(&mut self, (mut requests, mut ctxs, global_data): Self::SystemData) {
             requests.drain(..).for_each(|req| {
                 let entity_count = req.entities.len();
                 let ctx = ProtoStoreContext {
                     target_path: req.target_path,
+                    to_value: req.to_value,
+                    result: req.result,
                     entities: req.entities,
                     results: (0..entity_count).map(|_| StoringEntity::new(&*global_data.all_component_names)).collect(),
                     state: ProtoStoreState::Init
@@ -544,17 +2399,26 @@ pub(super) mod internal {
                     if entry.results.iter().all(|x|
                         x.components.values().all(|y| !y.is_await())) {
                         let entity_objs: Vec<_> = entry.results.drain(..)
-                            .map(|x| {
-                                let m: serde_json::Map<String, _> =
-                                    x.components.into_iter()
-                                        .flat_map(|(k, v)| v.unwrap().map(|v2| (k, v2)))
-                                        .collect();
-                                Value::Object(m)
+                            .zip(&entry.entities)
+                            .map(|(x, entity)| {
+                                let components = x.components.into_iter()
+                                    .flat_map(|(k, v)| v.unwrap().map(|v2| (k, v2)))
+                                    .collect();
+                                stored_entity(components, &migrations, unknowns.get(*entity))
                             })
                             .collect();
-                        let result_str = serde_json::to_string_pretty(&Value::Array(entity_objs)).expect("Failed serialize to JSON");
-                        let result_path = asset::get_fs_path(&entry.target_path);
-                        std::fs::write(result_path, result_str).unwrap();
+                        let stored = Value::Array(entity_objs);
+                        let result = if entry.to_value {
+                            Ok(stored)
+                        } else {
+                            write_proto_file(&entry.target_path, &stored)
+                                .map(|_| stored)
+                                .map_err(|e| ProtoError::new(&entry.target_path, e))
+                        };
+                        if let Err(e) = &result {
+                            error!("Failed to store proto {}", e);
+                        }
+                        *entry.result.lock().unwrap() = Poll::Ready(result);
 
                         entry.state = ProtoStoreState::Finished;
                     }
@@ -564,4 +2428,533 @@ pub(super) mod internal {
             ctxs.retain(|x| x.state != ProtoStoreState::Finished);
         }
     }
-}
\ No newline at end of file
+}
+
+/// A world running the proto systems with the default components, for testing modules built
+/// on them without a `Runtime`.
+#[cfg(test)]
+pub(crate) mod test_app {
+    use specs_hierarchy::HierarchySystem;
+
+    use super::internal::*;
+    use super::*;
+    use crate::ecs::{DEP_HIERARCHY, HasParent};
+
+    pub struct TestApp {
+        pub world: World,
+        dispatcher: Dispatcher<'static, 'static>
+    }
+
+    struct Registry {
+        world: World,
+        builder: DispatcherBuilder<'static, 'static>
+    }
+
+    impl ComponentS11nRegistry for Registry {
+        fn register_s11n<T: 'static + for<'a> ComponentS11n<'a> + Send + Clone>(&mut self, s11n: T) {
+            self.world.write_resource::<ProtoStoreGlobalData>().all_component_names.push(s11n.type_name());
+            self.world.write_resource::<ProtoMigrations>().register(&s11n);
+            self.builder.add(ComponentLoadSystem(s11n.clone()), "", &[DEP_PROTO_LOAD]);
+            self.builder.add(ComponentStoreSystem(s11n), "", &[DEP_PROTO_STORE]);
+        }
+    }
+
+    impl TestApp {
+
+        /// `setup` adds the systems under test, those named in `before_proto` run before
+        /// `DEP_PROTO_LOAD` and `DEP_PROTO_STORE`.
+        pub fn new(before_proto: &[&str], setup: impl FnOnce(&mut World, &mut DispatcherBuilder<'static, 'static>)) -> Self {
+            let mut world = World::new();
+            world.insert(ResManager::new());
+            world.insert(ProtoStoreGlobalData::default());
+            world.insert(ProtoMigrations::builtin());
+            world.insert(ProtoLoadRequests::new());
+            world.insert(ProtoLoadContexts::new());
+            world.insert(ProtoThreadPool(ThreadPool::new().unwrap()));
+
+            let mut builder = DispatcherBuilder::new();
+            builder.add(HierarchySystem::<HasParent>::new(&mut world), DEP_HIERARCHY, &[]);
+            setup(&mut world, &mut builder);
+            builder.add(ProtoLoadSystem::new(), DEP_PROTO_LOAD, before_proto);
+            builder.add(ProtoStoreSystem, DEP_PROTO_STORE, before_proto);
+
+            let mut registry = Registry { world, builder };
+            register_default_components(&mut registry);
+            let Registry { mut world, builder } = registry;
+            let mut dispatcher = builder.build();
+            dispatcher.setup(&mut world);
+            Self { world, dispatcher }
+        }
+
+        pub fn run(&mut self) {
+            self.dispatcher.dispatch(&self.world);
+            self.world.maintain();
+        }
+
+        /// Runs frames until `done`, giving the proto thread pool time to finish loads.
+        pub fn run_until(&mut self, mut done: impl FnMut(&World) -> bool) {
+            for _ in 0..1000 {
+                self.run();
+                if done(&self.world) {
+                    return
+                }
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            panic!("Not done after 1000 frames");
+        }
+
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use specs_derive::Component;
+
+    use super::internal::{ExpandedProto, ProtoLoadSystem, ProtoStoreGlobalData, stored_entity};
+    use super::*;
+
+    fn expand(files: &HashMap<&str, Value>, path: &str) -> Result<ExpandedProto, ProtoError> {
+        let mut ret = ExpandedProto::default();
+        ret.expand_file(path, &mut vec![], &ProtoMigrations::default(), &mut |p| Ok(files[p].clone()))?;
+        Ok(ret)
+    }
+
+    #[test]
+    fn nested_proto_with_overrides() {
+        let mut files = HashMap::new();
+        files.insert("goblin.json", json!([
+            { "Transform": { "pos": [0, 0, 0], "rot": [0, 0, 0, 1] }, "Hp": { "max": 10, "cur": 10 } },
+            { "Weapon": "club", "HasParent": { "entity_ix": 0 } }
+        ]));
+        files.insert("level.json", json!([
+            { "Level": {} },
+            { "$proto": "goblin.json", "overrides": { "Hp": { "cur": 5 }, "HasParent": { "entity_ix": 0 } } },
+            { "Marker": {}, "HasParent": { "entity_ix": 1 } }
+        ]));
+
+        let expanded = expand(&files, "level.json").unwrap();
+        assert_eq!(expanded.entities.len(), 4);
+        let level_scope = &expanded.scopes[0];
+        let goblin_scope = &expanded.scopes[1];
+
+        let goblin = &expanded.entities[level_scope[1]];
+        assert_eq!(level_scope[1], goblin_scope[0]);
+        assert_eq!(goblin.components["Hp"], json!({ "max": 10, "cur": 5 }));
+        // Overridden components resolve indices in the referencing file, others in their own
+        assert_eq!(goblin.component_scopes["HasParent"], (0, 1));
+        assert_eq!(goblin.component_scopes["Transform"], (1, 0));
+
+        let weapon = &expanded.entities[goblin_scope[1]];
+        assert_eq!(weapon.component_scopes["HasParent"], (1, 1));
+    }
+
+    #[test]
+    fn cyclic_proto_is_detected() {
+        let mut files = HashMap::new();
+        files.insert("a.json", json!([{ "$proto": "b.json" }]));
+        files.insert("b.json", json!([{ "X": {} }, { "$proto": "a.json" }]));
+        let err = expand(&files, "a.json").err().unwrap();
+        match err.kind {
+            ProtoErrorKind::CyclicReference(chain) => assert_eq!(chain, vec!["a.json", "b.json", "a.json"]),
+            _ => panic!("Unexpected error {}", err)
+        }
+    }
+
+    #[test]
+    fn invalid_entity_reports_location() {
+        let mut files = HashMap::new();
+        files.insert("a.json", json!([{ "X": {} }, { "$proto": "b.json" }]));
+        files.insert("b.json", json!([{ "X": {} }, 42]));
+        let err = expand(&files, "a.json").err().unwrap();
+        assert_eq!(err.path, "b.json");
+        assert_eq!(err.entity_idx, Some(1));
+    }
+
+    #[derive(Component, ProtoComponent)]
+    #[proto(name = "Door", schema)]
+    struct Door {
+        open: bool,
+        hint: Option<String>,
+        #[proto(entity)]
+        switch: Entity,
+        #[proto(entity)]
+        lock: Option<Entity>,
+        #[proto(resource)]
+        script: ResourceRef<String>,
+        #[proto(skip)]
+        timer: f32
+    }
+
+    #[test]
+    fn derive_proto_component() {
+        let mut world = World::new();
+        world.register::<Door>();
+        let entities: Vec<Entity> = (0..2).map(|_| world.create_entity().build()).collect();
+        let mut res_mgr = ResManager::new();
+        let script = res_mgr.add_with_path("open()".to_string(), "door.lua");
+
+        let data = json!({ "open": true, "switch": 1, "lock": null, "script": "door.lua" });
+        let door = Door::load(data.clone(), &mut ProtoLoadCtx { entities: &entities, res_mgr: &mut res_mgr }).unwrap();
+        assert!(door.open);
+        assert_eq!(door.hint, None);
+        assert_eq!(door.switch, entities[1]);
+        assert_eq!(door.lock, None);
+        assert!(door.script == script);
+        assert_eq!(door.timer, 0.);
+
+        let stored = door.store(&ProtoStoreCtx { entities: &entities, res_mgr: &res_mgr }).unwrap();
+        assert_eq!(stored, json!({ "open": true, "hint": null, "switch": 1, "lock": null, "script": "door.lua" }));
+
+        // An asset not added with a path fails the store instead of panicking
+        let anonymous = Door { script: res_mgr.add("close()".to_string()), ..door };
+        let err = anonymous.store(&ProtoStoreCtx { entities: &entities, res_mgr: &res_mgr }).err().unwrap();
+        assert!(err.to_string().starts_with("Field script"), "{}", err);
+
+        // `asset_ref` fields inside plain fields
+        let mut fields = serde_json::Map::new();
+        let ctx = ProtoStoreCtx { entities: &entities, res_mgr: &res_mgr };
+        ctx.write_field(&mut fields, "dialogue", &Dialogue { script: script.clone() }).unwrap();
+        assert_eq!(fields["dialogue"], json!({ "script": "door.lua" }));
+        let dialogue: Dialogue = ProtoLoadCtx { entities: &entities, res_mgr: &mut res_mgr }
+            .read_field(&mut fields, "dialogue").unwrap();
+        assert!(dialogue.script == script);
+
+        let err = Door::load(json!({ "open": true, "switch": 5, "script": "door.lua" }),
+                             &mut ProtoLoadCtx { entities: &entities, res_mgr: &mut res_mgr }).err().unwrap();
+        assert!(err.to_string().starts_with("Field switch"), "{}", err);
+    }
+
+    #[test]
+    fn binary_format_roundtrip() {
+        assert_eq!(ProtoFormat::from_path("levels/a.bproto"), ProtoFormat::Binary);
+        assert_eq!(ProtoFormat::from_path("levels/a.json"), ProtoFormat::Json);
+
+        let proto = json!([
+            { "Transform": { "pos": [0.5, 0, -1], "rot": [0, 0, 0, 1] }, "Name": "root" },
+            { "Transform": { "pos": [1, 2, 3], "rot": [0, 0, 0, 1] }, "HasParent": { "entity_ix": 0 } },
+            { "$proto": "goblin.json", "overrides": { "Hp": { "cur": 5 } } }
+        ]);
+        let bytes = ProtoFormat::Binary.encode(&proto).unwrap();
+        assert_eq!(ProtoFormat::Binary.decode(&bytes).unwrap(), proto);
+
+        let json_bytes = ProtoFormat::Json.encode(&proto).unwrap();
+        assert!(bytes.len() < json_bytes.len());
+        assert!(ProtoFormat::Binary.decode(&json_bytes).is_err());
+    }
+
+    fn door_migrations() -> ProtoMigrations {
+        let mut ret = ProtoMigrations::default();
+        ret.set_version("Door", 3);
+        ret.set_version("Transform", 1);
+        // v1 -> v2: `opened` renamed to `open`
+        ret.add("Door", 1, |mut v| {
+            if let Some(x) = v.as_object_mut().and_then(|m| m.remove("opened")) {
+                v["open"] = x;
+            }
+            Ok(v)
+        });
+        // v2 -> v3: `open` becomes `state`
+        ret.add("Door", 2, |mut v| {
+            match v.as_object_mut().and_then(|m| m.remove("open")) {
+                Some(Value::Bool(open)) => v["state"] = if open { "open" } else { "closed" }.into(),
+                Some(_) => return Err(ProtoErrorKind::Invalid("Expecting bool".to_string())),
+                None => ()
+            }
+            Ok(v)
+        });
+        ret
+    }
+
+    #[test]
+    fn migrate_component_versions() {
+        let migrations = door_migrations();
+
+        let mut entity = json!({ "Door": { "opened": true }, "Transform": {}, "Unknown": 1 });
+        assert!(migrations.upgrade_entity(entity.as_object_mut().unwrap()).unwrap());
+        assert_eq!(entity, json!({
+            "$versions": { "Door": 3 }, "Door": { "state": "open" }, "Transform": {}, "Unknown": 1
+        }));
+        // Already current
+        assert!(!migrations.upgrade_entity(entity.as_object_mut().unwrap()).unwrap());
+
+        let mut reference = json!({ "$proto": "door.json", "$versions": { "Door": 2 }, "overrides": { "Door": { "open": false } } });
+        assert!(migrations.upgrade_entity(reference.as_object_mut().unwrap()).unwrap());
+        assert_eq!(reference["overrides"], json!({ "Door": { "state": "closed" } }));
+
+        let mut newer = json!({ "$versions": { "Door": 4 }, "Door": {} });
+        assert!(migrations.upgrade_entity(newer.as_object_mut().unwrap()).is_err());
+
+        let mut broken = json!({ "$versions": { "Door": 2 }, "Door": { "open": 1 } });
+        match migrations.upgrade_entity(broken.as_object_mut().unwrap()).err().unwrap() {
+            ProtoErrorKind::Migration { component, from_version, .. } => assert_eq!((component.as_str(), from_version), ("Door", 2)),
+            e => panic!("Unexpected error {}", e)
+        }
+
+        // Nested protos are migrated by their own versions before overrides are applied
+        let mut files = HashMap::new();
+        files.insert("door.json", json!([{ "Door": { "opened": false } }]));
+        files.insert("level.json", json!([{ "$proto": "door.json", "$versions": { "Door": 3 }, "overrides": { "Door": { "state": "open" } } }]));
+        let mut expanded = ExpandedProto::default();
+        expanded.expand_file("level.json", &mut vec![], &migrations, &mut |p| Ok(files[p].clone())).unwrap();
+        assert_eq!(Value::Object(expanded.entities[0].components.clone()), json!({ "Door": { "state": "open" } }));
+
+        assert_eq!(migrations.versions_value(vec!["Door", "Transform"].into_iter()), Some(json!({ "Door": 3 })));
+        assert_eq!(migrations.versions_value(vec!["Transform"].into_iter()), None);
+    }
+
+    #[test]
+    fn upgrade_dir_all_or_nothing() {
+        let dir = std::env::temp_dir().join(format!("mu_upgrade_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, text: &str| std::fs::write(dir.join(name), text).unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        let migrations = door_migrations();
+
+        let old_door = r#"[{ "Door": { "opened": true } }]"#;
+        write("a.json", old_door);
+        write("b.json", r#"[{ "$versions": { "Door": 2 }, "Door": { "open": 1 } }]"#);
+        write("c.json", "[{");
+        let errors = migrations.upgrade_dir(&dir).err().unwrap();
+        let paths: Vec<&str> = errors.iter().map(|e| e.path.rsplit('/').next().unwrap()).collect();
+        assert_eq!(paths, vec!["c.json", "b.json"]);
+        assert_eq!(read("a.json"), old_door, "Nothing is written if any file fails");
+
+        std::fs::remove_file(dir.join("b.json")).unwrap();
+        std::fs::remove_file(dir.join("c.json")).unwrap();
+        let changed = migrations.upgrade_dir(&dir).unwrap();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(serde_json::from_str::<Value>(&read("a.json")).unwrap(),
+                   json!([{ "$versions": { "Door": 3 }, "Door": { "state": "open" } }]));
+        assert!(!dir.join("a.json.tmp").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn validate_against_schemas() {
+        let mut schemas = ProtoSchemas::builtin();
+        schemas.register(&ProtoComponentS11n::<Door>::new());
+        let combined = schemas.combined();
+        assert!(combined["items"]["properties"]["Door"]["properties"]["switch"].is_object());
+
+        let proto = json!([
+            { "Door": { "open": true, "switch": 1, "script": "door.lua" }, "Transform": { "pos": [0, 1, 2] } },
+            { "Door": { "open": "yes", "switch": -1, "script": "door.lua" }, "HasParent": { "entity_ix": 0 } },
+            { "Window": {}, "Name": "Skylight", "$versions": { "Door": 1 } },
+            { "$proto": "door.json", "overrides": { "Door": { "open": false }, "Windo": {} } },
+            { "Transform": { "pos": [0, 1] } }
+        ]);
+        let mut errors: Vec<_> = schemas.validate("level.json", &proto).into_iter()
+            .map(|e| (e.entity_idx.unwrap(), e.component.clone(), e.to_string()))
+            .collect();
+        errors.sort();
+        assert_eq!(errors.len(), 5, "{:?}", errors);
+        assert_eq!(errors[0].0, 1);
+        assert_eq!(errors[0].1.as_deref(), Some("Door"));
+        assert_eq!(errors[1].0, 1);
+        assert_eq!((errors[2].0, errors[2].1.as_deref()), (2, None));
+        assert_eq!(errors[2].2, "level.json, entity #2 (Skylight): Unknown component Window");
+        assert!(errors[3].2.contains("Unknown component Windo"), "{}", errors[3].2);
+        assert_eq!((errors[4].0, errors[4].1.as_deref()), (4, Some("Transform")));
+    }
+
+    #[derive(Serialize, Deserialize, Component)]
+    struct Turret {
+        target: Option<EntityRef>,
+        patrol: Vec<EntityRef>
+    }
+
+    #[test]
+    fn entity_ref_in_serde_types() {
+        let mut world = World::new();
+        let entities: Vec<Entity> = (0..3).map(|_| world.create_entity().build()).collect();
+        let outside = world.create_entity().build();
+
+        let data = json!({ "target": 2, "patrol": [0, 1, 0] });
+        let turret: Turret = with_proto_entities(&entities, || serde_json::from_value(data.clone())).unwrap();
+        assert_eq!(turret.target, Some(EntityRef(entities[2])));
+        assert_eq!(turret.patrol, vec![EntityRef(entities[0]), EntityRef(entities[1]), EntityRef(entities[0])]);
+        assert_eq!(with_proto_entities(&entities, || serde_json::to_value(&turret)).unwrap(), data);
+
+        // Reference to an entity that isn't stored becomes null
+        let turret = Turret { target: Some(EntityRef(outside)), patrol: vec![] };
+        let stored = with_proto_entities(&entities, || serde_json::to_value(&turret)).unwrap();
+        assert_eq!(stored, json!({ "target": null, "patrol": [] }));
+
+        let err = with_proto_entities(&entities, || serde_json::from_value::<Turret>(json!({ "target": 3, "patrol": [] })));
+        assert!(err.is_err());
+        assert!(serde_json::from_value::<Turret>(data).is_err());
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Dialogue {
+        #[serde(with = "asset_ref")]
+        script: ResourceRef<String>
+    }
+
+    #[test]
+    fn asset_ref_paths() {
+        let mut res_mgr = ResManager::new();
+        let script = res_mgr.add_with_path("Hello".to_string(), "dialogue/intro.txt");
+        let data = json!({ "script": "dialogue/intro.txt" });
+
+        let dialogue = Dialogue { script: script.clone() };
+        assert_eq!(asset_ref::with_res_mgr(&res_mgr, || serde_json::to_value(&dialogue)).unwrap(), data);
+
+        // Already loaded assets are looked up, with write access as well
+        let loaded: Dialogue = asset_ref::with_res_mgr(&res_mgr, || serde_json::from_value(data.clone())).unwrap();
+        assert!(loaded.script == script);
+        let loaded: Dialogue = asset_ref::with_res_mgr_mut(&mut res_mgr, || serde_json::from_value(data.clone())).unwrap();
+        assert!(loaded.script == script);
+
+        let missing = json!({ "script": "dialogue/missing.txt" });
+        assert!(asset_ref::with_res_mgr(&res_mgr, || serde_json::from_value::<Dialogue>(missing.clone())).is_err());
+        assert!(asset_ref::with_res_mgr_mut(&mut res_mgr, || serde_json::from_value::<Dialogue>(missing)).is_err());
+        assert!(serde_json::from_value::<Dialogue>(data).is_err());
+        assert!(serde_json::to_value(&dialogue).is_err());
+
+        // An asset not added with a path can't be stored, so the component is omitted
+        let anonymous = Dialogue { script: res_mgr.add("Bye".to_string()) };
+        assert!(asset_ref::with_res_mgr(&res_mgr, || serde_json::to_value(&anonymous)).is_err());
+        assert_eq!(store_default("Dialogue", &anonymous, &[], &res_mgr), None);
+
+        // Unit structs are stored as null, which isn't an omitted component
+        #[derive(Serialize)]
+        struct Marker;
+        assert_eq!(store_default("Marker", &Marker, &[], &res_mgr), Some(Value::Null));
+    }
+
+    #[test]
+    fn unknown_components_roundtrip() {
+        let mut world = World::new();
+        world.insert(ProtoLoadRequests::new());
+        world.insert(ProtoLoadContexts::new());
+        world.insert(ProtoMigrations::builtin());
+        world.insert(ResManager::new());
+        world.insert(ProtoStoreGlobalData::default());
+        let mut system = ProtoLoadSystem::new();
+        System::setup(&mut system, &mut world);
+
+        let proto = json!([
+            { "Gameplay": { "hp": 3 }, "$versions": { "Gameplay": 2 } },
+            { "Script": "door.lua" }
+        ]);
+        let req = ProtoLoadRequest::from_value("level.json", proto);
+        let result = req.result.clone();
+        world.write_resource::<ProtoLoadRequests>().push(req);
+        // Unknown components don't keep the load waiting
+        for _ in 0..3 {
+            system.run_now(&world);
+        }
+        let spawned = match &*result.lock().unwrap() {
+            Poll::Ready(Ok(spawned)) => spawned.clone(),
+            _ => panic!("Proto didn't finish loading")
+        };
+
+        let unknowns = world.read_storage::<UnknownComponents>();
+        let unknown = unknowns.get(spawned[0]).unwrap();
+        assert_eq!(Value::Object(unknown.components.clone()), json!({ "Gameplay": { "hp": 3 } }));
+        assert_eq!(Value::Object(unknown.versions.clone()), json!({ "Gameplay": 2 }));
+
+        let transform = ("Transform".to_string(), json!({ "pos": [1, 2, 3] }));
+        let migrations = world.fetch::<ProtoMigrations>();
+        assert_eq!(stored_entity(vec![transform.clone()], &migrations, Some(unknown)),
+                   json!({ "Transform": { "pos": [1, 2, 3] }, "Gameplay": { "hp": 3 }, "$versions": { "Gameplay": 2 } }));
+        assert_eq!(stored_entity(vec![], &migrations, unknowns.get(spawned[1])), json!({ "Script": "door.lua" }));
+
+        // Once the component is known, its stored data wins
+        let gameplay = ("Gameplay".to_string(), json!({ "hp": 1 }));
+        assert_eq!(stored_entity(vec![transform, gameplay], &migrations, Some(unknown)),
+                   json!({ "Transform": { "pos": [1, 2, 3] }, "Gameplay": { "hp": 1 } }));
+    }
+
+    #[test]
+    fn loading_batch_progress() {
+        let mut batch = LoadingBatch::new();
+        let a = batch.add(ProtoLoadRequest::new("a.json"));
+        let b = batch.add(ProtoLoadRequest::new("b.json"));
+        assert_eq!(batch.fraction(), 0.);
+
+        a.progress.add_components(3);
+        a.progress.complete_component();
+        let ticket = a.progress.start_asset();
+        assert_eq!(a.progress.components(), (1, 3));
+        assert_eq!(a.progress.assets(), (0, 1));
+        assert_eq!(a.progress.fraction(), 0.25);
+        drop(ticket);
+        assert_eq!(a.progress.assets(), (1, 1));
+
+        *b.result.lock().unwrap() = Poll::Ready(Ok(vec![]));
+        assert_eq!(batch.fraction(), 0.75);
+        assert!(!batch.is_finished());
+        assert!(batch.poll_finished().is_none());
+
+        *a.result.lock().unwrap() = Poll::Ready(Err(ProtoError::new("a.json", ProtoErrorKind::Invalid("x".to_string()))));
+        let results = batch.poll_finished().unwrap();
+        assert!(results[0].is_err());
+        assert_eq!(results[1].as_ref().unwrap(), &vec![]);
+        // The completion is reported once
+        assert!(batch.poll_finished().is_none());
+        assert_eq!(batch.fraction(), 1.);
+    }
+
+    #[test]
+    fn loading_batch_event() {
+        let mut world = World::new();
+        world.insert(LoadingBatches::new());
+        world.insert(EventChannel::<LoadingBatchFinished>::new());
+        let mut reader = world.write_resource::<EventChannel<LoadingBatchFinished>>().register_reader();
+        let mut batch = LoadingBatch::new();
+        let a = batch.add(ProtoLoadRequest::new("a.json"));
+        world.write_resource::<LoadingBatches>().push(("level".to_string(), batch));
+
+        LoadingBatchSystem.run_now(&world);
+        assert_eq!(world.read_resource::<EventChannel<LoadingBatchFinished>>().read(&mut reader).count(), 0);
+
+        *a.result.lock().unwrap() = Poll::Ready(Ok(vec![]));
+        LoadingBatchSystem.run_now(&world);
+        LoadingBatchSystem.run_now(&world);
+        let events = world.read_resource::<EventChannel<LoadingBatchFinished>>();
+        let finished: Vec<_> = events.read(&mut reader).collect();
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].name, "level");
+        assert!(finished[0].results[0].is_ok());
+        assert!(world.read_resource::<LoadingBatches>().is_empty());
+    }
+
+    #[test]
+    fn spawn_many_from_template() {
+        let mut res_mgr = ResManager::new();
+        let template = res_mgr.add_with_path(ProtoTemplate::new(json!([{ "Transform": {} }])), "bullet.json");
+        assert!(ProtoTemplate::load(&mut res_mgr, "bullet.json").unwrap() == template);
+
+        let requests = spawn_many(&res_mgr, &template, 3, |i, proto| {
+            proto[0]["Transform"]["pos"] = json!([i, 0, 0]);
+        });
+        assert_eq!(requests.len(), 3);
+        for (i, req) in requests.iter().enumerate() {
+            assert_eq!(req.path, "bullet.json");
+            assert_eq!(req.data, Some(json!([{ "Transform": { "pos": [i, 0, 0] } }])));
+        }
+        // The template itself is unchanged
+        assert_eq!(res_mgr.get(&template).data, json!([{ "Transform": {} }]));
+    }
+
+    #[test]
+    fn template_reads_modified_file() {
+        let path = std::env::temp_dir().join(format!("mu_template_test_{}.json", std::process::id()));
+        std::fs::write(&path, r#"[{ "Name": "Old" }]"#).unwrap();
+        let path_str = path.to_str().unwrap();
+        let mut res_mgr = ResManager::new();
+        let template = ProtoTemplate::load(&mut res_mgr, path_str).unwrap();
+        assert!(ProtoTemplate::load(&mut res_mgr, path_str).unwrap() == template);
+
+        std::fs::write(&path, r#"[{ "Name": "New" }]"#).unwrap();
+        // Modification times may be coarse
+        let later = res_mgr.get(&template).modified.unwrap() + std::time::Duration::from_secs(1);
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        assert!(ProtoTemplate::load(&mut res_mgr, path_str).unwrap() == template);
+        assert_eq!(res_mgr.get(&template).data, json!([{ "Name": "New" }]));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
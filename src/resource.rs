@@ -13,25 +13,74 @@ use std::io;
 use std::cell::{RefCell};
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicU32, Ordering};
+use std::hash::{Hash, Hasher};
+use std::fmt;
+use specs::shrev::EventChannel;
 
 pub type LocalResManager = ResourceManager<dyn ResPool>;
 pub type ResManager = ResourceManager<dyn ThreadedResPool>;
 
+/// A generational handle to a resource inside a `ResourcePool`.
+///
+/// A slot is reused after its resource is freed, so the handle also records the slot's generation
+/// and the pool it comes from. Accessing through a stale or foreign handle is caught instead of
+/// silently aliasing another resource.
 pub struct ResourceRef<T: 'static> {
     idx: usize,
+    generation: u32,
+    pool_id: u32,
     type_id: TypeId,
     ref_cnt: Arc<AtomicU32>,
     marker: PhantomData<T>
 }
 
+impl<T: 'static> ResourceRef<T> {
+
+    pub fn index(&self) -> usize {
+        self.idx
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Creates a `WeakResourceRef` which doesn't keep the resource loaded.
+    pub fn downgrade(&self) -> WeakResourceRef<T> {
+        WeakResourceRef {
+            idx: self.idx,
+            generation: self.generation,
+            pool_id: self.pool_id,
+            marker: PhantomData
+        }
+    }
+
+}
+
 impl<T: 'static> PartialEq for ResourceRef<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.idx == other.idx
+        self.idx == other.idx &&
+            self.generation == other.generation &&
+            self.pool_id == other.pool_id &&
+            self.type_id == other.type_id
     }
 }
 
 impl<T: 'static> Eq for ResourceRef<T> {}
 
+impl<T: 'static> Hash for ResourceRef<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.idx.hash(state);
+        self.generation.hash(state);
+        self.pool_id.hash(state);
+    }
+}
+
+impl<T: 'static> fmt::Debug for ResourceRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ResourceRef<{}>({}v{}@pool{})", type_name::<T>(), self.idx, self.generation, self.pool_id)
+    }
+}
+
 // PhantomData 只当做一个类型标记，实际上能够跨线程同步
 unsafe impl<T: 'static> Send for ResourceRef<T> {}
 unsafe impl<T: 'static> Sync for ResourceRef<T> {}
@@ -46,6 +95,8 @@ impl<T: 'static> Clone for ResourceRef<T> {
     fn clone(&self) -> Self {
         let ret = Self {
             idx: self.idx,
+            generation: self.generation,
+            pool_id: self.pool_id,
             type_id: self.type_id,
             marker: PhantomData,
             ref_cnt: self.ref_cnt.clone()
@@ -57,23 +108,179 @@ impl<T: 'static> Clone for ResourceRef<T> {
     }
 }
 
+/// A reference to a resource that doesn't count towards its ref count.
+///
+/// Use `ResourcePool::upgrade` or `ResourceManager::upgrade` to get a `ResourceRef` back, which
+/// fails once the resource has been freed by `cleanup`.
+pub struct WeakResourceRef<T: 'static> {
+    idx: usize,
+    generation: u32,
+    pool_id: u32,
+    marker: PhantomData<T>
+}
+
+unsafe impl<T: 'static> Send for WeakResourceRef<T> {}
+unsafe impl<T: 'static> Sync for WeakResourceRef<T> {}
+
+impl<T: 'static> Clone for WeakResourceRef<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: 'static> Copy for WeakResourceRef<T> {}
+
+impl<T: 'static> PartialEq for WeakResourceRef<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.idx == other.idx &&
+            self.generation == other.generation &&
+            self.pool_id == other.pool_id
+    }
+}
+
+impl<T: 'static> Eq for WeakResourceRef<T> {}
+
+impl<T: 'static> Hash for WeakResourceRef<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.idx.hash(state);
+        self.generation.hash(state);
+        self.pool_id.hash(state);
+    }
+}
+
+impl<T: 'static> fmt::Debug for WeakResourceRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WeakResourceRef<{}>({}v{}@pool{})", type_name::<T>(), self.idx, self.generation, self.pool_id)
+    }
+}
+
+impl<T: 'static> PartialEq<WeakResourceRef<T>> for ResourceRef<T> {
+    fn eq(&self, other: &WeakResourceRef<T>) -> bool {
+        self.downgrade() == *other
+    }
+}
+
+/// Lifetime events of resources in a `ResourcePool`.
+///
+/// Register a reader with `ResourcePool::events_mut().register_reader()`. Events are only recorded
+/// while at least one reader is registered.
+pub enum ResourceEvent<T: 'static> {
+    Created(WeakResourceRef<T>),
+    /// Content behind the ref is swapped with `ResourcePool::replace`.
+    Replaced(WeakResourceRef<T>),
+    /// The resource is dropped in `cleanup`. The ref can't be upgraded anymore.
+    Freed(WeakResourceRef<T>)
+}
+
 pub type ResourceKey = u64;
 
 struct ResourceEntry<T> {
     resource: T,
-    ref_cnt: Arc<AtomicU32>
+    ref_cnt: Arc<AtomicU32>,
+    /// The `cleanup` tick at which the resource was first seen unreferenced.
+    unused_since: Option<u64>,
+    /// Asset path the resource is loaded from, see `ResourcePool::add_with_path`.
+    path: Option<String>,
+    /// Bumped every time the content is swapped with `replace`.
+    version: u32
+}
+
+/// Estimated memory footprint of a resource, used by `RetentionPolicy::lru_bytes`.
+pub trait ResourceSize {
+    fn resource_size(&self) -> usize;
+}
+
+impl ResourceSize for String {
+    fn resource_size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl ResourceSize for Vec<u8> {
+    fn resource_size(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Decides when `ResourcePool::cleanup` frees a resource whose ref count has dropped to 0.
+///
+/// Resources kept alive this way can still be retrieved with `get_by_key` or `upgrade`, so
+/// reloading them is skipped.
+pub enum RetentionPolicy<T> {
+    /// Free at the first `cleanup` after being unreferenced. This is the default.
+    Immediate,
+    /// Keep unreferenced resources, freeing the least recently used ones once the total cost
+    /// of them exceeds `budget`.
+    Lru { budget: usize, cost: fn(&T) -> usize },
+    /// Only free with `purge_unused`.
+    KeepUntilPurge
+}
+
+impl<T> RetentionPolicy<T> {
+
+    pub fn lru_count(max_count: usize) -> Self {
+        RetentionPolicy::Lru {
+            budget: max_count,
+            cost: |_: &T| 1
+        }
+    }
+
 }
 
+impl<T: ResourceSize> RetentionPolicy<T> {
+
+    pub fn lru_bytes(max_bytes: usize) -> Self {
+        RetentionPolicy::Lru {
+            budget: max_bytes,
+            cost: T::resource_size
+        }
+    }
+
+}
+
+/// Unique id for every created pool, used to catch refs passed to a pool they don't belong to.
+static POOL_ID_COUNTER: AtomicU32 = AtomicU32::new(0);
+
 pub struct ResourcePool<T> where T: 'static {
+    id: u32,
     entries: Vec<Option<ResourceEntry<T>>>,
+    /// Generation of each slot, bumped every time the slot is freed.
+    generations: Vec<u32>,
     free_indices: Vec<usize>,
-    res_mapping: HashMap<ResourceKey, usize>
+    res_mapping: HashMap<ResourceKey, usize>,
+    events: EventChannel<ResourceEvent<T>>,
+    retention: RetentionPolicy<T>,
+    cleanup_tick: u64
 }
 
 impl<T: 'static + Send + Sync> ThreadedResPool for ResourcePool<T> {}
 
+/// Introspection data of a single resource, see `ResPool::stats`.
+#[derive(Clone, Debug)]
+pub struct ResourceEntryStats {
+    pub index: usize,
+    pub generation: u32,
+    pub ref_count: u32,
+    pub keys: Vec<ResourceKey>,
+    pub path: Option<String>
+}
+
+/// Introspection data of a `ResourcePool`.
+#[derive(Clone, Debug)]
+pub struct PoolStats {
+    pub type_name: &'static str,
+    pub live_count: usize,
+    pub free_slots: usize,
+    pub keyed_count: usize,
+    pub entries: Vec<ResourceEntryStats>
+}
+
 pub trait ResPool {
+    /// Frees unreferenced resources according to the pool's `RetentionPolicy`.
     fn cleanup(&mut self);
+    /// Frees all unreferenced resources regardless of the retention policy.
+    fn purge_unused(&mut self);
+    fn stats(&self) -> PoolStats;
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
 }
@@ -82,9 +289,13 @@ pub trait ThreadedResPool: ResPool + Send + Sync {}
 
 impl<T: 'static + LoadableAsset> ResourcePool<T> {
 
+    /// Loads the asset at `path`, or returns the already loaded one.
     pub fn load_asset(&mut self, path: &str) -> io::Result<ResourceRef<T>> {
+        if let Some(ret) = self.get_by_path(path) {
+            return Ok(ret)
+        }
         let asset = load_asset(path)?;
-        Ok(self.add(asset))
+        Ok(self.add_with_path(asset, path))
     }
 
 }
@@ -93,24 +304,71 @@ impl<T> ResourcePool<T> where T: 'static {
 
     pub fn new() -> Self {
         Self {
+            id: POOL_ID_COUNTER.fetch_add(1, Ordering::SeqCst),
             entries: vec![],
+            generations: vec![],
             free_indices: vec![],
-            res_mapping: HashMap::new()
+            res_mapping: HashMap::new(),
+            events: EventChannel::new(),
+            retention: RetentionPolicy::Immediate,
+            cleanup_tick: 0
+        }
+    }
+
+    pub fn set_retention_policy(&mut self, policy: RetentionPolicy<T>) {
+        self.retention = policy;
+    }
+
+    pub fn events(&self) -> &EventChannel<ResourceEvent<T>> {
+        &self.events
+    }
+
+    pub fn events_mut(&mut self) -> &mut EventChannel<ResourceEvent<T>> {
+        &mut self.events
+    }
+
+    fn make_ref(&self, idx: usize, ref_cnt: Arc<AtomicU32>) -> ResourceRef<T> {
+        ResourceRef {
+            idx,
+            generation: self.generations[idx],
+            pool_id: self.id,
+            type_id: TypeId::of::<T>(),
+            ref_cnt,
+            marker: PhantomData
         }
     }
 
     pub fn get_by_key(&self, k: ResourceKey) -> Option<ResourceRef<T>> {
         self.res_mapping.get(&k)
-            .map(|v| {
-                let entry = self.entries[*v].as_ref().unwrap();
+            .and_then(|v| self.entries[*v].as_ref().map(|entry| (*v, entry)))
+            .map(|(idx, entry)| {
                 entry.ref_cnt.fetch_add(1, Ordering::SeqCst);
+                self.make_ref(idx, entry.ref_cnt.clone())
+            })
+    }
 
-                ResourceRef {
-                    idx: *v,
-                    type_id: TypeId::of::<T>(),
-                    ref_cnt: entry.ref_cnt.clone(),
-                    marker: PhantomData
-                }
+    /// Same as `get_by_key`, but doesn't keep the resource alive.
+    pub fn get_weak_by_key(&self, k: ResourceKey) -> Option<WeakResourceRef<T>> {
+        self.res_mapping.get(&k)
+            .filter(|v| self.entries[**v].is_some())
+            .map(|v| WeakResourceRef {
+                idx: *v,
+                generation: self.generations[*v],
+                pool_id: self.id,
+                marker: PhantomData
+            })
+    }
+
+    /// Get a strong ref from `weak`, if the resource hasn't been freed yet.
+    pub fn upgrade(&self, weak: &WeakResourceRef<T>) -> Option<ResourceRef<T>> {
+        if weak.pool_id != self.id || self.generations.get(weak.idx) != Some(&weak.generation) {
+            return None
+        }
+
+        self.entries[weak.idx].as_ref()
+            .map(|entry| {
+                entry.ref_cnt.fetch_add(1, Ordering::SeqCst);
+                self.make_ref(weak.idx, entry.ref_cnt.clone())
             })
     }
 
@@ -120,63 +378,245 @@ impl<T> ResourcePool<T> where T: 'static {
         r
     }
 
+    /// Adds a resource loaded from `path`. It can be retrieved with `get_by_path`, and
+    /// `path_of` maps the ref back to the path.
+    pub fn add_with_path(&mut self, res: T, path: &str) -> ResourceRef<T> {
+        let r = self.add_by_key(res, get_path_hash(path));
+        self.entries[r.idx].as_mut().unwrap().path = Some(path.to_string());
+        r
+    }
+
+    pub fn get_by_path(&self, path: &str) -> Option<ResourceRef<T>> {
+        self.get_by_key(get_path_hash(path))
+    }
+
+    /// The asset path `res_ref` is added with, if any.
+    pub fn path_of(&self, res_ref: &ResourceRef<T>) -> Option<&str> {
+        if self.contains(res_ref) {
+            self.entries[res_ref.idx].as_ref().and_then(|x| x.path.as_deref())
+        } else {
+            None
+        }
+    }
+
     pub fn add(&mut self, res: T) -> ResourceRef<T> {
         let ref_cnt = Arc::new(AtomicU32::new(1));
         let resource_entry = ResourceEntry {
             resource: res,
-            ref_cnt: ref_cnt.clone()
+            ref_cnt: ref_cnt.clone(),
+            unused_since: None,
+            path: None,
+            version: 0
         };
-        let idx = if self.free_indices.is_empty() {
-            self.entries.push(Some(resource_entry));
-            self.entries.len() - 1
-        } else {
-            let idx = self.free_indices.remove(self.free_indices.len() - 1);
-            self.entries[idx] = Some(resource_entry);
-            idx
+        let idx = match self.free_indices.pop() {
+            Some(idx) => {
+                self.entries[idx] = Some(resource_entry);
+                idx
+            },
+            None => {
+                self.entries.push(Some(resource_entry));
+                self.generations.push(0);
+                self.entries.len() - 1
+            }
         };
 
-        ResourceRef {
-            idx,
-            type_id: TypeId::of::<T>(),
-            ref_cnt,
-            marker: PhantomData
+        let ret = self.make_ref(idx, ref_cnt);
+        if self.events.would_write() {
+            self.events.single_write(ResourceEvent::Created(ret.downgrade()));
+        }
+        ret
+    }
+
+    /// Swaps the content behind `res_ref` with `res`, returning the previous content.
+    ///
+    /// All refs to the resource stay valid and see the new content. The entry's `version` is
+    /// bumped so dependents caching derived data (e.g. GPU objects) know to rebuild.
+    pub fn replace(&mut self, res_ref: &ResourceRef<T>, res: T) -> T {
+        if !self.contains(res_ref) {
+            self.invalid_access(res_ref);
+        }
+        let entry = self.entries[res_ref.idx].as_mut().unwrap();
+        entry.version = entry.version.wrapping_add(1);
+        let prev = std::mem::replace(&mut entry.resource, res);
+        if self.events.would_write() {
+            self.events.single_write(ResourceEvent::Replaced(res_ref.downgrade()));
+        }
+        prev
+    }
+
+    /// Whether `res_ref` still points to a live resource of this pool.
+    pub fn contains(&self, res_ref: &ResourceRef<T>) -> bool {
+        res_ref.pool_id == self.id &&
+            res_ref.idx < self.entries.len() &&
+            self.generations[res_ref.idx] == res_ref.generation &&
+            self.entries[res_ref.idx].is_some()
+    }
+
+    /// Content version of the resource, changes whenever it's `replace`d.
+    /// Modification through `get_mut` doesn't count.
+    pub fn version(&self, res_ref: &ResourceRef<T>) -> u32 {
+        if !self.contains(res_ref) {
+            self.invalid_access(res_ref);
+        }
+        self.entries[res_ref.idx].as_ref().unwrap().version
+    }
+
+    pub fn try_get(&self, res_ref: &ResourceRef<T>) -> Option<&T> {
+        if self.contains(res_ref) {
+            self.entries[res_ref.idx].as_ref().map(|x| &x.resource)
+        } else {
+            None
+        }
+    }
+
+    pub fn try_get_mut(&mut self, res_ref: &ResourceRef<T>) -> Option<&mut T> {
+        if self.contains(res_ref) {
+            self.entries[res_ref.idx].as_mut().map(|x| &mut x.resource)
+        } else {
+            None
         }
     }
 
     pub fn get(&self, res_ref: &ResourceRef<T>) -> &T {
-        & (&self.entries[res_ref.idx]).as_ref().unwrap().resource
+        if !self.contains(res_ref) {
+            self.invalid_access(res_ref);
+        }
+        &self.entries[res_ref.idx].as_ref().unwrap().resource
     }
 
     pub fn get_mut(&mut self, res_ref: &ResourceRef<T>) -> &mut T {
-        &mut (&mut self.entries[res_ref.idx]).as_mut().unwrap().resource
+        if !self.contains(res_ref) {
+            self.invalid_access(res_ref);
+        }
+        &mut self.entries[res_ref.idx].as_mut().unwrap().resource
+    }
+
+    #[cold]
+    #[cfg(debug_assertions)]
+    fn invalid_access(&self, res_ref: &ResourceRef<T>) -> ! {
+        if res_ref.pool_id != self.id {
+            panic!("{:?} belongs to pool {}, but is accessed in pool {}", res_ref, res_ref.pool_id, self.id);
+        }
+        match self.entries.get(res_ref.idx) {
+            None => panic!("{:?} is out of range, pool size is {}", res_ref, self.entries.len()),
+            Some(_) if self.generations[res_ref.idx] != res_ref.generation =>
+                panic!("{:?} is stale, slot is now at generation {}", res_ref, self.generations[res_ref.idx]),
+            _ => panic!("{:?} has already been freed", res_ref),
+        }
+    }
+
+    #[cold]
+    #[cfg(not(debug_assertions))]
+    fn invalid_access(&self, res_ref: &ResourceRef<T>) -> ! {
+        panic!("Invalid resource access: {:?}", res_ref)
+    }
+}
+
+impl<T> ResourcePool<T> where T: 'static {
+
+    fn free(&mut self, ix: usize) {
+        info!("Cleanup asset of type {}", type_name::<T>());
+        self.entries[ix].take();
+        if self.events.would_write() {
+            self.events.single_write(ResourceEvent::Freed(WeakResourceRef {
+                idx: ix,
+                generation: self.generations[ix],
+                pool_id: self.id,
+                marker: PhantomData
+            }));
+        }
+        self.generations[ix] = self.generations[ix].wrapping_add(1);
+        self.free_indices.push(ix);
+    }
+
+    /// Indices of all unreferenced resources, least recently used first.
+    fn collect_unused(&mut self) -> Vec<usize> {
+        let tick = self.cleanup_tick;
+        let mut unused = vec![];
+        for (ix, item) in self.entries.iter_mut().enumerate() {
+            if let Some(entry) = item {
+                if entry.ref_cnt.load(Ordering::SeqCst) == 0 {
+                    let since = *entry.unused_since.get_or_insert(tick);
+                    unused.push((since, ix));
+                } else {
+                    entry.unused_since = None;
+                }
+            }
+        }
+
+        unused.sort();
+        unused.into_iter().map(|(_, ix)| ix).collect()
+    }
+
+    fn free_all(&mut self, indices: &[usize]) {
+        if indices.is_empty() {
+            return
+        }
+
+        for ix in indices {
+            self.free(*ix);
+        }
+
+        let entries = &self.entries;
+        self.res_mapping.retain(|_, v| entries[*v].is_some());
     }
+
 }
 
 impl<T> ResPool for ResourcePool<T> where T: 'static {
     fn cleanup(&mut self) {
-        let mut has_remove = false;
-        for (ix, item) in (&mut self.entries).iter_mut().enumerate() {
-            let need_remove = match item {
-                Some(x) if x.ref_cnt.load(Ordering::SeqCst) == 0 => true,
-                _ => false
-            };
-
-            if need_remove {
-                info!("Cleanup asset of type {}", type_name::<T>());
-                item.take();
-                self.free_indices.push(ix);
-                has_remove = true;
-            } else {
-                // info!("type {} ptr={:?}", type_name::<T>(),
-                //       (&item).as_ref().map(|x| x.ref_cnt.load(Ordering::SeqCst)));
+        self.cleanup_tick += 1;
+        let unused = self.collect_unused();
+
+        let to_free = match &self.retention {
+            RetentionPolicy::Immediate => unused,
+            RetentionPolicy::KeepUntilPurge => vec![],
+            RetentionPolicy::Lru { budget, cost } => {
+                let costs: Vec<usize> = unused.iter()
+                    .map(|ix| cost(&self.entries[*ix].as_ref().unwrap().resource))
+                    .collect();
+                let mut total: usize = costs.iter().sum();
+                let mut evict_count = 0;
+                while total > *budget {
+                    total -= costs[evict_count];
+                    evict_count += 1;
+                }
+
+                unused[..evict_count].to_vec()
             }
+        };
+
+        self.free_all(&to_free);
+    }
+
+    fn purge_unused(&mut self) {
+        let unused = self.collect_unused();
+        self.free_all(&unused);
+    }
+
+    fn stats(&self) -> PoolStats {
+        let mut keys: HashMap<usize, Vec<ResourceKey>> = HashMap::new();
+        for (k, ix) in &self.res_mapping {
+            keys.entry(*ix).or_default().push(*k);
         }
 
-        if has_remove {
-            self.res_mapping = self.res_mapping.iter()
-                .filter(|(_, v)| self.entries[**v].is_none())
-                .map(|(k, v)| (*k, *v))
-                .collect();
+        let entries: Vec<_> = self.entries.iter()
+            .enumerate()
+            .filter_map(|(ix, item)| item.as_ref().map(|entry| ResourceEntryStats {
+                index: ix,
+                generation: self.generations[ix],
+                ref_count: entry.ref_cnt.load(Ordering::SeqCst),
+                keys: keys.remove(&ix).unwrap_or_default(),
+                path: entry.path.clone()
+            }))
+            .collect();
+
+        PoolStats {
+            type_name: type_name::<T>(),
+            live_count: entries.len(),
+            free_slots: self.free_indices.len(),
+            keyed_count: self.res_mapping.len(),
+            entries
         }
     }
 
@@ -213,6 +653,13 @@ pub fn cleanup_local_resources() {
     })
 }
 
+/// Frees all unreferenced thread local resources, regardless of retention policy.
+pub fn purge_unused_local_resources() {
+    ALL_RESOURCES.with(|ref_cell| {
+        ref_cell.borrow_mut().purge_unused();
+    })
+}
+
 pub struct ResourceManager<R: ResPool + ?Sized> {
     map: HashMap<TypeId, Box<R>>
 }
@@ -244,6 +691,14 @@ impl ResourceManager<dyn ResPool> {
         pool.get_mut(&res_ref)
     }
 
+    pub fn replace<T: 'static>(&mut self, res_ref: &ResourceRef<T>, res: T) -> T {
+        self.get_pool_mut::<T>().replace(res_ref, res)
+    }
+
+    pub fn set_retention_policy<T: 'static>(&mut self, policy: RetentionPolicy<T>) {
+        self.get_pool_mut::<T>().set_retention_policy(policy);
+    }
+
 }
 
 impl ResourceManager<dyn ThreadedResPool> {
@@ -260,6 +715,14 @@ impl ResourceManager<dyn ThreadedResPool> {
         pool.add_by_key(res, key)
     }
 
+    pub fn add_with_path<T: 'static + Send + Sync>(&mut self, res: T, path: &str) -> ResourceRef<T> {
+        self.get_pool_mut::<T>().add_with_path(res, path)
+    }
+
+    pub fn load_asset<T: 'static + LoadableAsset + Send + Sync>(&mut self, path: &str) -> io::Result<ResourceRef<T>> {
+        self.get_pool_mut::<T>().load_asset(path)
+    }
+
     pub fn add<T: 'static + Send + Sync>(&mut self, res: T) -> ResourceRef<T> {
         let type_id = TypeId::of::<T>();
         if !self.map.contains_key(&type_id) {
@@ -285,6 +748,15 @@ impl ResourceManager<dyn ThreadedResPool> {
         pool.get_mut(&res_ref)
     }
 
+    /// Swaps the content behind `res_ref`, see `ResourcePool::replace`.
+    pub fn replace<T: 'static + Send + Sync>(&mut self, res_ref: &ResourceRef<T>, res: T) -> T {
+        self.get_pool_mut::<T>().replace(res_ref, res)
+    }
+
+    pub fn set_retention_policy<T: 'static + Send + Sync>(&mut self, policy: RetentionPolicy<T>) {
+        self.get_pool_mut::<T>().set_retention_policy(policy);
+    }
+
 }
 
 impl<R: ResPool + ?Sized> ResourceManager<R> {
@@ -299,10 +771,33 @@ impl<R: ResPool + ?Sized> ResourceManager<R> {
     }
 
     pub fn get<T: 'static>(&self, res_ref: &ResourceRef<T>) -> &T {
-        let pool: &ResourcePool<T> = self.get_pool().unwrap();
+        let pool: &ResourcePool<T> = self.get_pool()
+            .unwrap_or_else(|| panic!("No resource pool of type {}", type_name::<T>()));
         pool.get(res_ref)
     }
 
+    pub fn try_get<T: 'static>(&self, res_ref: &ResourceRef<T>) -> Option<&T> {
+        self.get_pool::<T>().and_then(|pool| pool.try_get(res_ref))
+    }
+
+    pub fn version<T: 'static>(&self, res_ref: &ResourceRef<T>) -> u32 {
+        let pool: &ResourcePool<T> = self.get_pool()
+            .unwrap_or_else(|| panic!("No resource pool of type {}", type_name::<T>()));
+        pool.version(res_ref)
+    }
+
+    pub fn upgrade<T: 'static>(&self, weak: &WeakResourceRef<T>) -> Option<ResourceRef<T>> {
+        self.get_pool::<T>().and_then(|pool| pool.upgrade(weak))
+    }
+
+    pub fn get_by_path<T: 'static>(&self, path: &str) -> Option<ResourceRef<T>> {
+        self.get_pool::<T>().and_then(|pool| pool.get_by_path(path))
+    }
+
+    pub fn path_of<T: 'static>(&self, res_ref: &ResourceRef<T>) -> Option<&str> {
+        self.get_pool::<T>().and_then(|pool| pool.path_of(res_ref))
+    }
+
     pub fn get_by_key<T: 'static>(&self, key: ResourceKey) -> Option<ResourceRef<T>> {
         if let Some(pool) = self.get_pool::<T>() {
             pool.get_by_key(key)
@@ -312,12 +807,249 @@ impl<R: ResPool + ?Sized> ResourceManager<R> {
     }
 
     pub fn cleanup(&mut self) {
-        for (_, v) in &mut self.map {
+        for v in self.map.values_mut() {
             v.cleanup();
         }
     }
+
+    /// Statistics of every pool, sorted by type name.
+    pub fn stats(&self) -> Vec<PoolStats> {
+        let mut ret: Vec<_> = self.map.values().map(|x| x.stats()).collect();
+        ret.sort_by_key(|x| x.type_name);
+        ret
+    }
+
+    /// Frees every unreferenced resource, e.g. behind a loading screen when switching scenes.
+    pub fn purge_unused(&mut self) {
+        for v in self.map.values_mut() {
+            v.purge_unused();
+        }
+    }
 }
 
 thread_local! {
 static ALL_RESOURCES: RefCell<ResourceManager<dyn ResPool>> = RefCell::new(ResourceManager::new());
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn slot_reuse_invalidates_stale_ref() {
+        let mut pool: ResourcePool<i32> = ResourcePool::new();
+        let r0 = pool.add(1);
+        let stale = ResourceRef { ref_cnt: Arc::new(AtomicU32::new(1)), ..r0.clone() };
+        drop(r0);
+        pool.cleanup();
+
+        let r1 = pool.add(2);
+        assert_eq!(r1.index(), stale.index(), "Freed slot should be reused");
+        assert_ne!(r1.generation(), stale.generation());
+        assert!(r1 != stale);
+
+        assert_eq!(pool.try_get(&stale), None);
+        assert_eq!(pool.try_get(&r1), Some(&2));
+    }
+
+    #[test]
+    #[should_panic(expected = "is stale")]
+    fn stale_access_panics() {
+        let mut pool: ResourcePool<i32> = ResourcePool::new();
+        let r0 = pool.add(1);
+        let stale = ResourceRef { ref_cnt: Arc::new(AtomicU32::new(1)), ..r0.clone() };
+        drop(r0);
+        pool.cleanup();
+        let _r1 = pool.add(2);
+
+        pool.get(&stale);
+    }
+
+    #[test]
+    fn cross_pool_ref_is_rejected() {
+        let mut pool_a: ResourcePool<i32> = ResourcePool::new();
+        let mut pool_b: ResourcePool<i32> = ResourcePool::new();
+        let ra = pool_a.add(1);
+        let rb = pool_b.add(2);
+
+        assert_eq!(ra.index(), rb.index());
+        assert!(ra != rb);
+        assert_eq!(pool_b.try_get(&ra), None);
+        assert_eq!(pool_a.try_get(&ra), Some(&1));
+    }
+
+    #[test]
+    fn cleanup_keeps_mapping_of_live_resources() {
+        let mut pool: ResourcePool<i32> = ResourcePool::new();
+        let dropped = pool.add_by_key(1, 100);
+        let kept = pool.add_by_key(2, 200);
+        drop(dropped);
+        pool.cleanup();
+
+        assert!(pool.get_by_key(100).is_none());
+        assert_eq!(pool.get_by_key(200).as_ref(), Some(&kept));
+
+        // Reusing the freed slot must not resurrect the old key
+        let _r = pool.add(3);
+        assert!(pool.get_by_key(100).is_none());
+    }
+
+    #[test]
+    fn path_reverse_lookup() {
+        let mut pool: ResourcePool<i32> = ResourcePool::new();
+        let r = pool.add_with_path(1, "config/a.json");
+        let anonymous = pool.add(2);
+
+        assert_eq!(pool.path_of(&r), Some("config/a.json"));
+        assert_eq!(pool.path_of(&anonymous), None);
+        assert_eq!(pool.get_by_path("config/a.json").as_ref(), Some(&r));
+
+        let weak = r.downgrade();
+        drop(r);
+        pool.cleanup();
+        assert!(pool.upgrade(&weak).is_none());
+        assert!(pool.get_by_path("config/a.json").is_none());
+    }
+
+    #[test]
+    fn ref_count_across_threads() {
+        let mut pool: ResourcePool<String> = ResourcePool::new();
+        let r = pool.add("shared".to_string());
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let local = r.clone();
+                thread::spawn(move || {
+                    let clones: Vec<_> = (0..100).map(|_| local.clone()).collect();
+                    drop(clones);
+                    local
+                })
+            })
+            .collect();
+        let returned: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        assert_eq!(r.ref_cnt.load(Ordering::SeqCst), 9);
+        drop(returned);
+        assert_eq!(r.ref_cnt.load(Ordering::SeqCst), 1);
+
+        pool.cleanup();
+        assert!(pool.contains(&r));
+
+        let probe = ResourceRef { ref_cnt: Arc::new(AtomicU32::new(1)), ..r.clone() };
+        drop(r);
+        pool.cleanup();
+        assert!(!pool.contains(&probe));
+    }
+
+    #[test]
+    fn weak_ref_does_not_keep_resource() {
+        let mut pool: ResourcePool<i32> = ResourcePool::new();
+        let r = pool.add_by_key(1, 100);
+        let weak = r.downgrade();
+        assert_eq!(pool.get_weak_by_key(100), Some(weak));
+
+        let upgraded = pool.upgrade(&weak).unwrap();
+        assert!(upgraded == r);
+        drop(upgraded);
+        drop(r);
+        pool.cleanup();
+
+        assert!(pool.upgrade(&weak).is_none());
+        assert!(pool.get_weak_by_key(100).is_none());
+    }
+
+    #[test]
+    fn lifetime_events() {
+        let mut pool: ResourcePool<i32> = ResourcePool::new();
+        let mut reader = pool.events_mut().register_reader();
+
+        let r = pool.add(1);
+        assert_eq!(pool.version(&r), 0);
+        assert_eq!(pool.replace(&r, 2), 1);
+        assert_eq!(pool.version(&r), 1);
+        assert_eq!(pool.get(&r), &2);
+        let weak = r.downgrade();
+        drop(r);
+        pool.cleanup();
+
+        let events: Vec<_> = pool.events().read(&mut reader)
+            .map(|ev| match ev {
+                ResourceEvent::Created(w) => ("created", *w),
+                ResourceEvent::Replaced(w) => ("replaced", *w),
+                ResourceEvent::Freed(w) => ("freed", *w),
+            })
+            .collect();
+        assert_eq!(events, vec![("created", weak), ("replaced", weak), ("freed", weak)]);
+    }
+
+    #[test]
+    fn lru_retention_evicts_oldest() {
+        let mut pool: ResourcePool<String> = ResourcePool::new();
+        pool.set_retention_policy(RetentionPolicy::lru_bytes(8));
+
+        let a = pool.add_by_key("aaaa".to_string(), 1);
+        let b = pool.add_by_key("bbbb".to_string(), 2);
+        let c = pool.add_by_key("cccc".to_string(), 3);
+        drop(a);
+        pool.cleanup();
+        drop(b);
+        pool.cleanup();
+        assert!(pool.get_weak_by_key(1).is_some());
+        assert!(pool.get_weak_by_key(2).is_some());
+
+        drop(c);
+        pool.cleanup();
+        assert!(pool.get_weak_by_key(1).is_none(), "Least recently used should be evicted");
+        assert!(pool.get_weak_by_key(2).is_some());
+        assert!(pool.get_weak_by_key(3).is_some());
+
+        pool.purge_unused();
+        assert!(pool.get_weak_by_key(2).is_none());
+        assert!(pool.get_weak_by_key(3).is_none());
+    }
+
+    #[test]
+    fn keep_until_purge() {
+        let mut pool: ResourcePool<i32> = ResourcePool::new();
+        pool.set_retention_policy(RetentionPolicy::KeepUntilPurge);
+
+        let weak = pool.add_by_key(1, 1).downgrade();
+        pool.cleanup();
+        let revived = pool.upgrade(&weak).expect("Should be retained");
+        drop(revived);
+
+        pool.purge_unused();
+        assert!(pool.upgrade(&weak).is_none());
+    }
+
+    #[test]
+    fn manager_stats() {
+        let mut res_mgr = ResManager::new();
+        let a = res_mgr.add_with_path("a".to_string(), "text/a.txt");
+        let b = res_mgr.add_by_key("b".to_string(), 7);
+        let _b2 = b.clone();
+        let dropped = res_mgr.add(1i32);
+        drop(dropped);
+        res_mgr.cleanup();
+
+        let stats = res_mgr.stats();
+        assert_eq!(stats.iter().map(|x| x.type_name).collect::<Vec<_>>(), vec![type_name::<String>(), type_name::<i32>()]);
+
+        let ints = &stats[1];
+        assert_eq!((ints.live_count, ints.free_slots, ints.keyed_count), (0, 1, 0));
+        assert!(ints.entries.is_empty());
+
+        let strings = &stats[0];
+        assert_eq!((strings.live_count, strings.free_slots, strings.keyed_count), (2, 0, 2));
+        let entry_a = strings.entries.iter().find(|x| x.index == a.index()).unwrap();
+        assert_eq!(entry_a.ref_count, 1);
+        assert_eq!(entry_a.path.as_deref(), Some("text/a.txt"));
+        assert_eq!(entry_a.keys, vec![get_path_hash("text/a.txt")]);
+        let entry_b = strings.entries.iter().find(|x| x.index == b.index()).unwrap();
+        assert_eq!(entry_b.ref_count, 2);
+        assert_eq!(entry_b.generation, b.generation());
+        assert_eq!(entry_b.keys, vec![7]);
+        assert_eq!(entry_b.path, None);
+    }
+}
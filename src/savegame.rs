@@ -0,0 +1,707 @@
+//! Save games: registered resources and serializable entities stored in numbered slots.
+//!
+//! A slot is a single json file `slot_<n>.json` in `SaveSlots::dir`, holding `SaveMeta`, the
+//! resources registered with `InitContextSaveGameExt::add_save_resource` and the entities as a
+//! proto. References between saved entities, also `EntityRef`s in resources, stay valid after
+//! loading. Files are replaced atomically, a crash during saving leaves the old slot intact.
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use specs::hibitset::BitSetLike;
+use specs::prelude::*;
+use specs_hierarchy::Hierarchy;
+
+use internal::*;
+
+use crate::{InitContext, InsertInfo, Module};
+use crate::asset::write_atomic;
+use crate::ecs::{despawn_recursive, HasParent, Time};
+use crate::proto::*;
+use crate::scene::{scene_order, SceneExclude, SceneOrderData};
+
+pub static DEP_SAVEGAME: &str = "savegame";
+
+/// Seconds played, stored in `SaveMeta::play_time` and restored when loading.
+#[derive(Default)]
+pub struct PlayTime(pub f64);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SaveMeta {
+    pub slot: u32,
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    /// `PlayTime` when saved.
+    pub play_time: f64,
+    /// File name of the thumbnail in `SaveSlots::dir`.
+    pub thumbnail: Option<String>
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    meta: SaveMeta,
+    resources: serde_json::Map<String, Value>,
+    entities: Value
+}
+
+#[derive(Debug)]
+pub enum SaveGameError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Proto(ProtoError),
+    Resource {
+        name: String,
+        error: serde_json::Error
+    }
+}
+
+impl fmt::Display for SaveGameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveGameError::Io(e) => write!(f, "{}", e),
+            SaveGameError::Json(e) => write!(f, "{}", e),
+            SaveGameError::Proto(e) => write!(f, "{}", e),
+            SaveGameError::Resource { name, error } => write!(f, "resource {}: {}", name, error)
+        }
+    }
+}
+
+impl std::error::Error for SaveGameError {}
+
+impl From<io::Error> for SaveGameError {
+    fn from(e: io::Error) -> Self {
+        SaveGameError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SaveGameError {
+    fn from(e: serde_json::Error) -> Self {
+        SaveGameError::Json(e)
+    }
+}
+
+/// The save slots, in directory "saves" by default.
+pub struct SaveSlots {
+    pub dir: PathBuf
+}
+
+impl Default for SaveSlots {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("saves")
+        }
+    }
+}
+
+impl SaveSlots {
+
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into()
+        }
+    }
+
+    pub fn slot_path(&self, slot: u32) -> PathBuf {
+        self.dir.join(format!("slot_{}.json", slot))
+    }
+
+    fn thumbnail_name(slot: u32) -> String {
+        format!("slot_{}.png", slot)
+    }
+
+    /// Metadata of all slots, sorted by slot. Slots that can't be read are skipped with a warning.
+    pub fn list(&self) -> io::Result<Vec<SaveMeta>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(x) => x,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e)
+        };
+
+        #[derive(Deserialize)]
+        struct MetaOnly {
+            meta: SaveMeta
+        }
+
+        let mut ret = vec![];
+        for entry in entries {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            let slot: u32 = match name.strip_prefix("slot_").and_then(|x| x.strip_suffix(".json")) {
+                Some(x) => match x.parse() {
+                    Ok(slot) => slot,
+                    Err(_) => continue
+                },
+                None => continue
+            };
+            let result = std::fs::read(self.slot_path(slot))
+                .map_err(SaveGameError::from)
+                .and_then(|bytes| Ok(serde_json::from_slice::<MetaOnly>(&bytes)?));
+            match result {
+                Ok(x) => ret.push(x.meta),
+                Err(e) => warn!("Skipping save slot {}: {}", name, e)
+            }
+        }
+        ret.sort_by_key(|x| x.slot);
+        Ok(ret)
+    }
+
+    /// Deletes the slot and its thumbnail. Deleting an empty slot is fine.
+    pub fn delete(&self, slot: u32) -> io::Result<()> {
+        for path in [self.slot_path(slot), self.dir.join(Self::thumbnail_name(slot))] {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => ()
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&self, slot: u32) -> Result<SaveFile, SaveGameError> {
+        let bytes = std::fs::read(self.slot_path(slot))?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// The thumbnail is written first, so a slot never refers to a missing one. Without a
+    /// thumbnail, the one of the overwritten slot is deleted after writing the slot.
+    fn write(&self, file: &SaveFile, thumbnail: Option<&[u8]>) -> Result<(), SaveGameError> {
+        std::fs::create_dir_all(&self.dir)?;
+        if let (Some(bytes), Some(name)) = (thumbnail, &file.meta.thumbnail) {
+            write_atomic(&self.dir.join(name), bytes)?;
+        }
+        write_atomic(&self.slot_path(file.meta.slot), &serde_json::to_vec(file)?)?;
+        if file.meta.thumbnail.is_none() {
+            match std::fs::remove_file(self.dir.join(Self::thumbnail_name(file.meta.slot))) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => ()
+            }
+        }
+        Ok(())
+    }
+
+}
+
+pub type SaveGameResult = Arc<Mutex<Poll<Result<SaveMeta, SaveGameError>>>>;
+
+pub struct SaveSlotRequest {
+    pub slot: u32,
+    /// Save only these entities and their descendants, instead of every entity with a
+    /// serializable component. `SceneExclude` entities are never saved.
+    pub roots: Option<Vec<Entity>>,
+    /// PNG encoded image.
+    pub thumbnail: Option<Vec<u8>>,
+    pub result: SaveGameResult
+}
+
+impl SaveSlotRequest {
+
+    pub fn new(slot: u32) -> Self {
+        Self {
+            slot,
+            roots: None,
+            thumbnail: None,
+            result: Arc::new(Mutex::new(Poll::Pending))
+        }
+    }
+
+    pub fn with_roots(mut self, roots: &[Entity]) -> Self {
+        self.roots = Some(roots.to_vec());
+        self
+    }
+
+    pub fn with_thumbnail(mut self, png: Vec<u8>) -> Self {
+        self.thumbnail = Some(png);
+        self
+    }
+
+}
+
+/// Spawns the saved entities and restores the resources. Once spawned, every other entity
+/// with a serializable component is deleted, except `SceneExclude` ones. If the slot's entities
+/// fail to spawn, the world is kept as it is.
+pub struct LoadSlotRequest {
+    pub slot: u32,
+    pub result: SaveGameResult
+}
+
+impl LoadSlotRequest {
+
+    pub fn new(slot: u32) -> Self {
+        Self {
+            slot,
+            result: Arc::new(Mutex::new(Poll::Pending))
+        }
+    }
+
+}
+
+pub enum SaveGameRequest {
+    Save(SaveSlotRequest),
+    Load(LoadSlotRequest)
+}
+
+/// Requests are handled in order, the slot file is read or written a few frames later.
+pub type SaveGameRequests = Vec<SaveGameRequest>;
+
+enum SaveGameTask {
+    Saving {
+        req: SaveSlotRequest,
+        entities: ProtoStoreResult,
+        resources_id: u32
+    },
+    Spawning {
+        req: LoadSlotRequest,
+        file: SaveFile,
+        spawned: ProtoLoadResult,
+        /// Deleted once the slot's entities are spawned, kept if that fails.
+        old: Vec<Entity>
+    },
+    Restoring {
+        req: LoadSlotRequest,
+        meta: SaveMeta,
+        resources_id: u32
+    }
+}
+
+#[derive(Default)]
+struct SaveGameSystem {
+    /// Requests waiting for `SerializableEntityQuery`.
+    waiting: Vec<SaveGameRequest>,
+    tasks: Vec<SaveGameTask>,
+    counter: u32
+}
+
+impl SaveGameSystem {
+
+    fn push_resources(&mut self, contexts: &mut SaveResourceContexts, entities: Vec<Entity>, op: SaveResourceOp) -> u32 {
+        let id = self.counter;
+        self.counter += 1;
+        contexts.push(SaveResourceContext {
+            id,
+            entities,
+            op,
+            errors: vec![]
+        });
+        id
+    }
+
+}
+
+impl<'a> System<'a> for SaveGameSystem {
+    type SystemData = (
+        Write<'a, SaveGameRequests>,
+        Write<'a, SaveResourceContexts>,
+        ReadExpect<'a, SaveResourceNames>,
+        ReadExpect<'a, SaveSlots>,
+        Write<'a, PlayTime>,
+        ReadExpect<'a, Time>,
+        Write<'a, SerializableEntityQuery>,
+        WriteExpect<'a, ProtoLoadRequests>,
+        Write<'a, ProtoStoreRequests>,
+        ReadExpect<'a, Hierarchy<HasParent>>,
+        ReadStorage<'a, HasParent>,
+        ReadStorage<'a, SceneExclude>,
+        SceneOrderData<'a>,
+        Entities<'a>
+    );
+
+    fn run(&mut self, (mut requests, mut contexts, names, slots, mut play_time, time, mut query,
+        mut load_requests, mut store_requests, hierarchy, parents, excludes, order_data, entities): Self::SystemData) {
+        play_time.0 += time.get_delta_time() as f64;
+
+        // Query was issued last frame and is now filled by the ComponentStoreSystems
+        if query.ready && !self.waiting.is_empty() {
+            let serializable: Vec<Entity> = (&query.result).iter()
+                .map(|id| entities.entity(id))
+                .filter(|e| entities.is_alive(*e) && !excludes.contains(*e))
+                .collect();
+
+            for req in std::mem::take(&mut self.waiting) {
+                match req {
+                    SaveGameRequest::Save(req) => {
+                        let targets = match &req.roots {
+                            Some(roots) => roots.iter()
+                                .flat_map(|r| std::iter::once(*r).chain(hierarchy.all_children_iter(*r)))
+                                .filter(|e| !excludes.contains(*e))
+                                .collect::<HashSet<_>>()
+                                .into_iter()
+                                .collect(),
+                            None => serializable.clone()
+                        };
+                        let ordered = scene_order(targets, |e| parents.get(e).map(|x| x.parent), |e| order_data.key(e));
+                        let store = ProtoStoreRequest::to_value(&ordered);
+                        let result = store.result.clone();
+                        store_requests.push(store);
+                        let resources_id = self.push_resources(&mut contexts, ordered,
+                                                               SaveResourceOp::Store(serde_json::Map::new()));
+                        self.tasks.push(SaveGameTask::Saving { req, entities: result, resources_id });
+                    },
+                    SaveGameRequest::Load(req) => match slots.read(req.slot) {
+                        Ok(file) => {
+                            let path = slots.slot_path(req.slot).to_string_lossy().to_string();
+                            let load = ProtoLoadRequest::from_value(&path, file.entities.clone());
+                            let spawned = load.result.clone();
+                            load_requests.push(load);
+                            self.tasks.push(SaveGameTask::Spawning { req, file, spawned, old: serializable.clone() });
+                        },
+                        Err(e) => {
+                            error!("Failed to load save slot {}: {}", req.slot, e);
+                            *req.result.lock().unwrap() = Poll::Ready(Err(e));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut tasks = vec![];
+        for task in std::mem::take(&mut self.tasks) {
+            match task {
+                SaveGameTask::Saving { req, entities, resources_id } => {
+                    let ix = contexts.iter().position(|x| x.id == resources_id).unwrap();
+                    let resources_done = match &contexts[ix].op {
+                        SaveResourceOp::Store(values) => values.len() == names.0.len(),
+                        SaveResourceOp::Load(_) => unreachable!()
+                    };
+                    if !resources_done || entities.lock().unwrap().is_pending() {
+                        tasks.push(SaveGameTask::Saving { req, entities, resources_id });
+                        continue
+                    }
+
+                    let ctx = contexts.remove(ix);
+                    let result = match (ctx.errors.into_iter().next(), ctx.op) {
+                        (Some(e), _) => Err(e),
+                        (None, SaveResourceOp::Store(resources)) => {
+                            let entities = match std::mem::replace(&mut *entities.lock().unwrap(), Poll::Pending) {
+                                Poll::Ready(v) => v,
+                                Poll::Pending => unreachable!()
+                            };
+                            let entities = match entities {
+                                Ok(v) => v,
+                                Err(e) => {
+                                    *req.result.lock().unwrap() = Poll::Ready(Err(SaveGameError::Proto(e)));
+                                    continue
+                                }
+                            };
+                            let file = SaveFile {
+                                meta: SaveMeta {
+                                    slot: req.slot,
+                                    timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0),
+                                    play_time: play_time.0,
+                                    thumbnail: req.thumbnail.as_ref().map(|_| SaveSlots::thumbnail_name(req.slot))
+                                },
+                                resources,
+                                entities
+                            };
+                            slots.write(&file, req.thumbnail.as_deref()).map(|_| file.meta)
+                        },
+                        (None, SaveResourceOp::Load(_)) => unreachable!()
+                    };
+                    if let Err(e) = &result {
+                        error!("Failed to save slot {}: {}", req.slot, e);
+                    }
+                    *req.result.lock().unwrap() = Poll::Ready(result);
+                },
+                SaveGameTask::Spawning { req, mut file, spawned, old } => {
+                    let spawned = match std::mem::replace(&mut *spawned.lock().unwrap(), Poll::Pending) {
+                        Poll::Pending => {
+                            tasks.push(SaveGameTask::Spawning { req, file, spawned: spawned.clone(), old });
+                            continue
+                        },
+                        Poll::Ready(x) => x
+                    };
+                    match spawned {
+                        Ok(spawned) => {
+                            // Also deletes runtime children without serializable components
+                            for e in old {
+                                despawn_recursive(&entities, &hierarchy, e);
+                            }
+                            // Resources of modules that aren't loaded are dropped
+                            file.resources.retain(|k, _| {
+                                let known = names.0.contains(&k.as_str());
+                                if !known {
+                                    warn!("Save slot {}: unknown resource {}", req.slot, k);
+                                }
+                                known
+                            });
+                            play_time.0 = file.meta.play_time;
+                            let resources_id = self.push_resources(&mut contexts, spawned,
+                                                                   SaveResourceOp::Load(file.resources));
+                            tasks.push(SaveGameTask::Restoring { req, meta: file.meta, resources_id });
+                        },
+                        Err(e) => {
+                            error!("Failed to load save slot {}: {}", req.slot, e);
+                            *req.result.lock().unwrap() = Poll::Ready(Err(SaveGameError::Proto(e)));
+                        }
+                    }
+                },
+                SaveGameTask::Restoring { req, meta, resources_id } => {
+                    let ix = contexts.iter().position(|x| x.id == resources_id).unwrap();
+                    let done = match &contexts[ix].op {
+                        SaveResourceOp::Load(values) => values.is_empty(),
+                        SaveResourceOp::Store(_) => unreachable!()
+                    };
+                    if !done {
+                        tasks.push(SaveGameTask::Restoring { req, meta, resources_id });
+                        continue
+                    }
+
+                    let ctx = contexts.remove(ix);
+                    let result = match ctx.errors.into_iter().next() {
+                        Some(e) => {
+                            error!("Failed to load save slot {}: {}", req.slot, e);
+                            Err(e)
+                        },
+                        None => Ok(meta)
+                    };
+                    *req.result.lock().unwrap() = Poll::Ready(result);
+                }
+            }
+        }
+        self.tasks = tasks;
+
+        if !requests.is_empty() {
+            self.waiting.extend(requests.drain(..));
+            query.requested = true;
+        }
+    }
+}
+
+pub(super) struct SaveGameModule;
+
+impl Module for SaveGameModule {
+    fn init(&self, ctx: &mut InitContext) {
+        ctx.init_data.world.insert(SaveGameRequests::new());
+        if ctx.init_data.world.try_fetch::<SaveSlots>().is_none() {
+            ctx.init_data.world.insert(SaveSlots::default());
+        }
+        if ctx.init_data.world.try_fetch::<SaveResourceNames>().is_none() {
+            ctx.init_data.world.insert(SaveResourceNames(vec![]));
+        }
+        // `InitContext::dispatch` doesn't allow before deps
+        ctx.group_normal.dispatch(InsertInfo::new(DEP_SAVEGAME).before(&[DEP_PROTO_LOAD, DEP_PROTO_STORE]),
+                                  |_, i| i.insert(SaveGameSystem::default()));
+    }
+}
+
+pub trait InitContextSaveGameExt {
+    /// Saves the resource in save games under `name`. It's serialized with `serde_json`, and
+    /// `EntityRef`s in it refer to the saved entities.
+    fn add_save_resource<T>(&mut self, name: &'static str)
+        where T: Serialize + DeserializeOwned + Default + Send + Sync + 'static;
+}
+
+impl InitContextSaveGameExt for InitContext {
+    fn add_save_resource<T>(&mut self, name: &'static str)
+        where T: Serialize + DeserializeOwned + Default + Send + Sync + 'static {
+        if self.init_data.world.try_fetch::<SaveResourceNames>().is_none() {
+            self.init_data.world.insert(SaveResourceNames(vec![]));
+        }
+        {
+            let mut names = self.init_data.world.write_resource::<SaveResourceNames>();
+            // Saving would wait for both to be stored under the same name
+            if names.0.contains(&name) {
+                error!("Save resource {} is already added, ignoring it", name);
+                return
+            }
+            names.0.push(name);
+        }
+        self.dispatch(InsertInfo::default().after(&[DEP_SAVEGAME]),
+                      move |_, i| i.insert(SaveResourceSystem::<T>::new(name)));
+    }
+}
+
+pub(super) mod internal {
+    use super::*;
+
+    /// Names of the resources added with `add_save_resource`.
+    pub struct SaveResourceNames(pub Vec<&'static str>);
+
+    pub enum SaveResourceOp {
+        /// Filled with the value of each resource.
+        Store(serde_json::Map<String, Value>),
+        /// Each resource takes its value out.
+        Load(serde_json::Map<String, Value>)
+    }
+
+    pub struct SaveResourceContext {
+        pub id: u32,
+        /// Saved or spawned entities, for `EntityRef`s.
+        pub entities: Vec<Entity>,
+        pub op: SaveResourceOp,
+        pub errors: Vec<SaveGameError>
+    }
+
+    pub type SaveResourceContexts = Vec<SaveResourceContext>;
+
+    pub struct SaveResourceSystem<T> {
+        name: &'static str,
+        marker: PhantomData<T>
+    }
+
+    impl<T> SaveResourceSystem<T> {
+        pub fn new(name: &'static str) -> Self {
+            Self {
+                name,
+                marker: PhantomData
+            }
+        }
+    }
+
+    impl<'a, T> System<'a> for SaveResourceSystem<T>
+        where T: Serialize + DeserializeOwned + Default + Send + Sync + 'static {
+        type SystemData = (Write<'a, SaveResourceContexts>, Write<'a, T>);
+
+        fn run(&mut self, (mut contexts, mut resource): Self::SystemData) {
+            for ctx in &mut *contexts {
+                let name = self.name;
+                let result = match &mut ctx.op {
+                    SaveResourceOp::Store(values) => {
+                        if values.contains_key(name) {
+                            continue
+                        }
+                        let result = with_proto_entities(&ctx.entities, || serde_json::to_value(&*resource));
+                        values.insert(name.to_string(), result.as_ref().ok().cloned().unwrap_or(Value::Null));
+                        result.map(|_| ())
+                    },
+                    SaveResourceOp::Load(values) => match values.remove(name) {
+                        Some(v) => with_proto_entities(&ctx.entities, || serde_json::from_value(v))
+                            .map(|x| *resource = x),
+                        None => continue
+                    }
+                };
+                if let Err(error) = result {
+                    ctx.errors.push(SaveGameError::Resource { name: name.to_string(), error });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::name::Name;
+    use crate::proto::test_app::TestApp;
+    use super::*;
+
+    #[test]
+    fn slot_files() {
+        let dir = std::env::temp_dir().join(format!("mu_savegame_test_{}", std::process::id()));
+        let slots = SaveSlots::new(&dir);
+        assert_eq!(slots.list().unwrap(), vec![]);
+
+        let file = |slot, thumbnail: Option<&str>| SaveFile {
+            meta: SaveMeta {
+                slot,
+                timestamp: 1600000000,
+                play_time: 12.5,
+                thumbnail: thumbnail.map(|x| x.to_string())
+            },
+            resources: serde_json::Map::new(),
+            entities: Value::Array(vec![])
+        };
+        slots.write(&file(3, Some("slot_3.png")), Some(&[1, 2, 3])).unwrap();
+        slots.write(&file(1, None), None).unwrap();
+        // Overwriting leaves no temporary file behind
+        slots.write(&file(1, None), None).unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a slot").unwrap();
+
+        let list = slots.list().unwrap();
+        assert_eq!(list.iter().map(|x| x.slot).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(list[1].thumbnail.as_deref(), Some("slot_3.png"));
+        assert_eq!(std::fs::read(dir.join("slot_3.png")).unwrap(), vec![1, 2, 3]);
+        assert_eq!(slots.read(1).unwrap().meta, list[0]);
+        assert!(!dir.join("slot_1.json.tmp").exists());
+
+        // Overwriting without a thumbnail deletes the old one
+        slots.write(&file(3, None), None).unwrap();
+        assert!(!dir.join("slot_3.png").exists());
+
+        slots.delete(3).unwrap();
+        slots.delete(5).unwrap();
+        assert!(!dir.join("slot_3.png").exists());
+        assert_eq!(slots.list().unwrap().len(), 1);
+
+        // Crashed after writing the temporary file, before renaming it
+        std::fs::write(dir.join("slot_1.json.tmp"), "{ \"meta\": ").unwrap();
+        assert_eq!(slots.list().unwrap(), vec![list[0].clone()]);
+        assert_eq!(slots.read(1).unwrap().meta, list[0]);
+        // Failing to write the temporary file
+        std::fs::remove_file(dir.join("slot_1.json.tmp")).unwrap();
+        std::fs::create_dir(dir.join("slot_1.json.tmp")).unwrap();
+        let mut newer = file(1, None);
+        newer.meta.play_time = 99.;
+        assert!(slots.write(&newer, None).is_err());
+        assert_eq!(slots.read(1).unwrap().meta, list[0]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[derive(Serialize, Deserialize, Default)]
+    struct Party {
+        leader: Option<EntityRef>,
+        members: Vec<EntityRef>
+    }
+
+    #[test]
+    fn save_and_load_slot() {
+        let dir = std::env::temp_dir().join(format!("mu_savegame_load_test_{}", std::process::id()));
+        let mut app = TestApp::new(&[DEP_SAVEGAME], |world, builder| {
+            world.insert(SaveSlots::new(&dir));
+            world.insert(SaveResourceNames(vec!["party"]));
+            world.insert(Time::default());
+            builder.add(SaveGameSystem::default(), DEP_SAVEGAME, &[]);
+            builder.add(SaveResourceSystem::<Party>::new("party"), "", &[DEP_SAVEGAME]);
+        });
+        let mut request = |app: &mut TestApp, req: SaveGameRequest| {
+            let result = match &req {
+                SaveGameRequest::Save(x) => x.result.clone(),
+                SaveGameRequest::Load(x) => x.result.clone()
+            };
+            app.world.write_resource::<SaveGameRequests>().push(req);
+            app.run_until(|_| result.lock().unwrap().is_ready());
+            let ok = matches!(&*result.lock().unwrap(), Poll::Ready(Ok(_)));
+            ok
+        };
+
+        let hero = app.world.create_entity().with(Name::new("Hero")).build();
+        let ally = app.world.create_entity().with(Name::new("Ally")).build();
+        let runtime_child = app.world.create_entity().with(HasParent::new(hero)).build();
+        app.world.insert(Party { leader: Some(EntityRef(hero)), members: vec![EntityRef(ally), EntityRef(hero)] });
+        app.run();
+
+        assert!(request(&mut app, SaveGameRequest::Save(SaveSlotRequest::new(1))));
+
+        // A slot that fails to spawn keeps the world as it is
+        let corrupt = SaveFile {
+            meta: SaveMeta { slot: 2, timestamp: 0, play_time: 0., thumbnail: None },
+            resources: serde_json::Map::new(),
+            entities: serde_json::json!([{ "$proto": "mu_savegame_missing.json" }])
+        };
+        app.world.read_resource::<SaveSlots>().write(&corrupt, None).unwrap();
+        assert!(!request(&mut app, SaveGameRequest::Load(LoadSlotRequest::new(2))));
+        app.run();
+        assert!(app.world.is_alive(hero) && app.world.is_alive(ally) && app.world.is_alive(runtime_child));
+        assert_eq!(app.world.read_resource::<Party>().leader, Some(EntityRef(hero)));
+
+        *app.world.write_resource::<Party>() = Party::default();
+        assert!(request(&mut app, SaveGameRequest::Load(LoadSlotRequest::new(1))));
+        app.run();
+
+        assert!(!app.world.is_alive(hero));
+        assert!(!app.world.is_alive(ally));
+        assert!(!app.world.is_alive(runtime_child), "Load should delete runtime children");
+
+        let party = app.world.read_resource::<Party>();
+        let names = app.world.read_storage::<Name>();
+        let name_of = |x: &EntityRef| names.get(x.0).map(|x| x.0.as_str());
+        assert_eq!(party.leader.as_ref().and_then(name_of), Some("Hero"));
+        assert_eq!(party.members.iter().map(name_of).collect::<Vec<_>>(), vec![Some("Ally"), Some("Hero")]);
+        assert_eq!(party.members[1], party.leader.unwrap());
+        drop((party, names));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
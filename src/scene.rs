@@ -0,0 +1,314 @@
+//! Saving and loading the whole world, or parts of it, as a proto file.
+//!
+//! A scene file uses the same format as other protos. Entities are ordered with parents before
+//! their children, see `scene_order`, and component keys are sorted, so saving the same world
+//! twice, or saving a scene right after loading it, gives identical output.
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+
+use specs::hibitset::BitSetLike;
+use specs::prelude::*;
+use specs_derive::Component;
+use specs_hierarchy::Hierarchy;
+
+use crate::{InitContext, InsertInfo, Module};
+use crate::ecs::{despawn_recursive, HasParent, SiblingOrder};
+use crate::name::Name;
+use crate::proto::*;
+
+pub static DEP_SCENE: &str = "scene";
+
+/// Marks an entity (e.g. camera, editor entities) that is neither saved in scenes nor
+/// removed when loading a scene with `clear_world`.
+#[derive(Component, Default)]
+#[storage(NullStorage)]
+pub struct SceneExclude;
+
+pub struct SceneSaveRequest {
+    pub target_path: String,
+    /// Save only these entities and their descendants, instead of every entity with a
+    /// serializable component.
+    pub roots: Option<Vec<Entity>>,
+    /// See `ProtoStoreRequest::result`.
+    pub result: ProtoStoreResult
+}
+
+impl SceneSaveRequest {
+
+    pub fn all(target_path: &str) -> Self {
+        Self {
+            target_path: target_path.to_string(),
+            roots: None,
+            result: Arc::new(Mutex::new(Poll::Pending))
+        }
+    }
+
+    pub fn with_roots(roots: &[Entity], target_path: &str) -> Self {
+        Self {
+            roots: Some(roots.to_vec()),
+            ..Self::all(target_path)
+        }
+    }
+
+}
+
+pub struct SceneLoadRequest {
+    pub path: String,
+    /// Delete every entity with a serializable component before loading.
+    pub clear_world: bool,
+    pub result: ProtoLoadResult,
+    pub progress: ProtoLoadProgress
+}
+
+impl SceneLoadRequest {
+
+    pub fn new(path: &str, clear_world: bool) -> Self {
+        let req = ProtoLoadRequest::new(path);
+        Self {
+            path: req.path,
+            clear_world,
+            result: req.result,
+            progress: req.progress
+        }
+    }
+
+    pub fn handle(&self) -> ProtoLoadHandle {
+        ProtoLoadHandle {
+            result: self.result.clone(),
+            progress: self.progress.clone()
+        }
+    }
+
+}
+
+pub enum SceneRequest {
+    Save(SceneSaveRequest),
+    Load(SceneLoadRequest)
+}
+
+/// Requests are handled in order, one frame after being pushed.
+pub type SceneRequests = Vec<SceneRequest>;
+
+/// What `scene_order` sorts roots and siblings by.
+#[derive(SystemData)]
+pub(crate) struct SceneOrderData<'a> {
+    load_indices: ReadStorage<'a, LoadIndex>,
+    orders: ReadStorage<'a, SiblingOrder>,
+    names: ReadStorage<'a, Name>
+}
+
+impl<'a> SceneOrderData<'a> {
+
+    /// The `LoadIndex`, so a loaded scene is saved in its own order, then `SiblingOrder` and
+    /// `Name`. Entity ids come last, they are reused in any order after deleting entities.
+    pub fn key(&self, entity: Entity) -> (usize, i32, Option<String>, u32) {
+        (self.load_indices.get(entity).map(|x| x.0).unwrap_or(usize::MAX),
+         self.orders.get(entity).map(|x| x.0).unwrap_or(0),
+         self.names.get(entity).map(|x| x.0.clone()),
+         entity.id())
+    }
+
+}
+
+/// Orders entities with parents before children, roots and siblings sorted by `key_of`.
+pub(crate) fn scene_order<K: Ord>(mut entities: Vec<Entity>, parent_of: impl Fn(Entity) -> Option<Entity>,
+                                  key_of: impl Fn(Entity) -> K) -> Vec<Entity> {
+    entities.sort_by_cached_key(|e| key_of(*e));
+    let set: HashSet<Entity> = entities.iter().copied().collect();
+
+    let mut roots = vec![];
+    let mut children: HashMap<Entity, Vec<Entity>> = HashMap::new();
+    for e in &entities {
+        match parent_of(*e).filter(|p| set.contains(p)) {
+            Some(p) => children.entry(p).or_default().push(*e),
+            None => roots.push(*e)
+        }
+    }
+
+    let mut ret = Vec::with_capacity(entities.len());
+    let mut stack: Vec<Entity> = roots.into_iter().rev().collect();
+    while let Some(e) = stack.pop() {
+        ret.push(e);
+        if let Some(c) = children.get(&e) {
+            stack.extend(c.iter().rev());
+        }
+    }
+
+    // Entities in a parent cycle can't be reached from any root
+    if ret.len() < entities.len() {
+        let visited: HashSet<Entity> = ret.iter().copied().collect();
+        ret.extend(entities.into_iter().filter(|e| !visited.contains(e)));
+    }
+    ret
+}
+
+#[derive(Default)]
+struct SceneSystem {
+    waiting: Vec<SceneRequest>
+}
+
+impl<'a> System<'a> for SceneSystem {
+    type SystemData = (
+        Write<'a, SceneRequests>,
+        Write<'a, SerializableEntityQuery>,
+        WriteExpect<'a, ProtoLoadRequests>,
+        Write<'a, ProtoStoreRequests>,
+        ReadExpect<'a, Hierarchy<HasParent>>,
+        ReadStorage<'a, HasParent>,
+        ReadStorage<'a, SceneExclude>,
+        SceneOrderData<'a>,
+        Entities<'a>
+    );
+
+    fn run(&mut self, (mut requests, mut query, mut load_requests, mut store_requests,
+        hierarchy, parents, excludes, order_data, entities): Self::SystemData) {
+        // Query was issued last frame and is now filled by the ComponentStoreSystems
+        if query.ready {
+            let serializable: Vec<Entity> = (&query.result).iter()
+                .map(|id| entities.entity(id))
+                .filter(|e| entities.is_alive(*e) && !excludes.contains(*e))
+                .collect();
+
+            for req in self.waiting.drain(..) {
+                match req {
+                    SceneRequest::Save(req) => {
+                        let targets = match req.roots {
+                            Some(roots) => roots.iter()
+                                .flat_map(|r| std::iter::once(*r).chain(hierarchy.all_children_iter(*r)))
+                                .filter(|e| !excludes.contains(*e))
+                                .collect::<HashSet<_>>()
+                                .into_iter()
+                                .collect(),
+                            None => serializable.clone()
+                        };
+                        let ordered = scene_order(targets, |e| parents.get(e).map(|x| x.parent), |e| order_data.key(e));
+                        store_requests.push(ProtoStoreRequest {
+                            result: req.result,
+                            ..ProtoStoreRequest::new(&ordered, &req.target_path)
+                        });
+                    },
+                    SceneRequest::Load(req) => {
+                        if req.clear_world {
+                            // Also deletes runtime children without serializable components
+                            for e in &serializable {
+                                despawn_recursive(&entities, &hierarchy, *e);
+                            }
+                        }
+                        load_requests.push(ProtoLoadRequest {
+                            path: req.path,
+                            data: None,
+                            instance: false,
+                            result: req.result,
+                            progress: req.progress
+                        });
+                    }
+                }
+            }
+        }
+
+        if !requests.is_empty() {
+            self.waiting.extend(requests.drain(..));
+            query.requested = true;
+        }
+    }
+}
+
+pub(super) struct SceneModule;
+
+impl Module for SceneModule {
+    fn init(&self, ctx: &mut InitContext) {
+        ctx.init_data.world.insert(SceneRequests::new());
+        // `InitContext::dispatch` doesn't allow before deps
+        ctx.group_normal.dispatch(InsertInfo::new(DEP_SCENE).before(&[DEP_PROTO_LOAD, DEP_PROTO_STORE]),
+                                  |_, i| i.insert(SceneSystem::default()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::proto::test_app::TestApp;
+    use super::*;
+
+    #[test]
+    fn parents_before_children() {
+        let mut world = World::new();
+        let e: Vec<Entity> = (0..5).map(|_| world.create_entity().build()).collect();
+        // e0 <- e3 <- e1, e2 <- e4; e4's parent e2 is the only root besides e0
+        let parents: HashMap<Entity, Entity> = vec![(e[3], e[0]), (e[1], e[3]), (e[4], e[2])]
+            .into_iter().collect();
+
+        let input = vec![e[4], e[1], e[2], e[0], e[3]];
+        let ordered = scene_order(input, |x| parents.get(&x).copied(), |x| x.id());
+        assert_eq!(ordered, vec![e[0], e[3], e[1], e[2], e[4]]);
+
+        // Parent outside of the set makes the entity a root
+        let ordered = scene_order(vec![e[1], e[4]], |x| parents.get(&x).copied(), |x| x.id());
+        assert_eq!(ordered, vec![e[1], e[4]]);
+    }
+
+    #[test]
+    fn save_after_load_is_identical() {
+        let dir = std::env::temp_dir().join(format!("mu_scene_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+        let scene = json!([
+            { "Name": "Level" },
+            { "Name": "Door", "HasParent": { "entity_ix": 0 }, "SiblingOrder": 1,
+              "Transform": { "pos": [1.5, 0.0, -2.0], "rot": [0.0, 0.0, 0.0, 1.0], "scale": [1.0, 1.0, 1.0] } },
+            { "Name": "Switch", "HasParent": { "entity_ix": 1 }, "Tags": ["interactive"] },
+            { "Name": "Floor", "HasParent": { "entity_ix": 0 } },
+            { "Name": "Sky" }
+        ]);
+        std::fs::write(path("scene.json"), ProtoFormat::Json.encode(&scene).unwrap()).unwrap();
+
+        let mut app = TestApp::new(&[DEP_SCENE], |_, builder| {
+            builder.add(SceneSystem::default(), DEP_SCENE, &[]);
+        });
+        // Loaded entities reuse these ids, out of order
+        let placeholders: Vec<Entity> = (0..5).map(|_| app.world.create_entity().build()).collect();
+        for ix in &[3, 0, 4, 1, 2] {
+            app.world.delete_entity(placeholders[*ix]).unwrap();
+        }
+        app.world.maintain();
+        let mut load = |app: &mut TestApp, from: &str, to: &str| {
+            let req = SceneLoadRequest::new(from, true);
+            let result = req.result.clone();
+            app.world.write_resource::<SceneRequests>().push(SceneRequest::Load(req));
+            app.run_until(|_| result.lock().unwrap().is_ready());
+
+            let req = SceneSaveRequest::all(to);
+            let result = req.result.clone();
+            app.world.write_resource::<SceneRequests>().push(SceneRequest::Save(req));
+            app.run_until(|_| result.lock().unwrap().is_ready());
+            assert!(matches!(&*result.lock().unwrap(), Poll::Ready(Ok(_))));
+        };
+
+        load(&mut app, &path("scene.json"), &path("first.json"));
+        let level = (&app.world.entities(), &app.world.read_storage::<Name>()).join()
+            .find(|(_, x)| x.0 == "Level")
+            .unwrap().0;
+        // A child created at runtime, without serializable components
+        let runtime_child = app.world.create_entity().with(HasParent::new(level)).build();
+        app.run();
+
+        // Reloading reuses entity ids in a different order
+        load(&mut app, &path("first.json"), &path("second.json"));
+        assert!(!app.world.is_alive(runtime_child), "clear_world should delete runtime children");
+
+        let first = std::fs::read(path("first.json")).unwrap();
+        assert_eq!(ProtoFormat::Json.decode(&first).unwrap(), scene);
+        assert_eq!(first, std::fs::read(path("second.json")).unwrap());
+
+        // A failed write is reported, not a panic
+        let req = SceneSaveRequest::all(&path("missing/third.json"));
+        let result = req.result.clone();
+        app.world.write_resource::<SceneRequests>().push(SceneRequest::Save(req));
+        app.run_until(|_| result.lock().unwrap().is_ready());
+        assert!(matches!(&*result.lock().unwrap(), Poll::Ready(Err(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,9 +1,10 @@
 use crate::math::*;
 use std::ops::{AddAssign, Mul};
 use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 
 /// Generic RGBA color.
-#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Color {
     pub r: f32,
     pub g: f32,